@@ -0,0 +1,66 @@
+use std::{io::Cursor, path::Path};
+
+use image::codecs::jpeg::JpegEncoder;
+
+use crate::config::ArtworkConfig;
+
+/// File names looked for in a show folder, in order of preference
+pub const CANDIDATES: [&str; 6] = [
+    "poster.jpg",
+    "poster.png",
+    "folder.jpg",
+    "folder.png",
+    "fanart.jpg",
+    "fanart.png",
+];
+
+/// Artwork ready to be uploaded as a webhook attachment
+#[derive(Clone)]
+pub struct Artwork {
+    pub file_name: String,
+    pub mime: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// Read artwork from disk, downscaling it to a JPEG if it exceeds `max_bytes`
+pub fn load(path: &Path, config: &ArtworkConfig) -> Option<Artwork> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Failed to read artwork {}: {err}", path.display());
+            return None;
+        },
+    };
+
+    if bytes.len() <= config.max_bytes() {
+        let is_png = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+
+        return Some(if is_png {
+            Artwork { file_name: String::from("poster.png"), mime: "image/png", bytes }
+        } else {
+            Artwork { file_name: String::from("poster.jpg"), mime: "image/jpeg", bytes }
+        });
+    }
+
+    let image = match image::load_from_memory(&bytes) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("Failed to decode artwork {}: {err}", path.display());
+            return None;
+        },
+    };
+
+    let resized = image.thumbnail(config.max_dimension(), config.max_dimension()).into_rgb8();
+
+    let mut downscaled = Vec::new();
+    JpegEncoder::new_with_quality(Cursor::new(&mut downscaled), 85)
+        .encode_image(&resized)
+        .expect("Failed to encode artwork");
+
+    if downscaled.len() > config.max_bytes() {
+        eprintln!("Artwork {} is too large even after downscaling, skipping it", path.display());
+        return None;
+    }
+
+    Some(Artwork { file_name: String::from("poster.jpg"), mime: "image/jpeg", bytes: downscaled })
+}
@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
+use crate::{inspect::OutputFormat, transfer::ImportMode};
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
@@ -14,8 +16,37 @@ pub struct Cli {
 pub enum Commands {
     /// Check a directory for changes
     Check(CheckArgs),
+    /// Keep watching a directory, notifying as soon as something changes
+    Watch(CheckArgs),
+    /// Check a directory on the schedule from the configuration
+    Run(CheckArgs),
+    /// Send a sample of every change kind to every configured target
+    TestNotify(TestNotifyArgs),
+    /// List the runs that changed something and what they changed
+    History(HistoryArgs),
+    /// Compare the shows of two runs
+    Diff(DiffArgs),
+    /// List the tracked shows with their season and episode counts
+    List(ListArgs),
+    /// Show the seasons of a tracked show
+    Show(ShowArgs),
+    /// List the tracked shows whose title matches a regex
+    Search(SearchArgs),
+    /// Drop a show from the state, so the next check announces it again
+    Forget(TitleArgs),
+    /// Keep tracking a show but never notify about it
+    Ignore(IgnoreArgs),
+    /// Accept what is on disk without notifying about it
+    MarkSeen(ProfileArgs),
+    /// Send the latest change of a show again
+    Resend(TitleArgs),
+    /// Write the state of profiles to a JSON document
+    Export(ExportArgs),
+    /// Load the state of profiles from an exported JSON document
+    Import(ImportArgs),
     Datadir,
-    Cleardata,
+    /// Delete the state of all profiles, or only of the given ones
+    Cleardata(CleardataArgs),
 }
 
 #[derive(Args, Debug)]
@@ -24,7 +55,195 @@ pub struct CheckArgs {
     #[arg(short, long)]
     pub config: PathBuf,
 
-    /// Dont fire discord webhook, only persist directories.
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// Dont send any notifications, only persist directories.
     #[arg(short, long)]
     pub no_webhook: bool,
-}
\ No newline at end of file
+}
+
+#[derive(Args, Debug)]
+pub struct TestNotifyArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// Only print the rendered payloads, dont send anything.
+    #[arg(short, long)]
+    pub dry_run: bool,
+
+    /// Existing forum post to send the samples to, Discord targets are skipped without it if forum_threads is enabled
+    #[arg(short, long)]
+    pub thread_id: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// Only show the latest runs
+    #[arg(short, long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// Run to compare from
+    #[arg(long)]
+    pub from: u64,
+
+    /// Run to compare to, the latest one if not given
+    #[arg(long)]
+    pub to: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// Cleaned title of the show, ignoring case
+    pub title: String,
+
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// Regex to match titles against, ignoring case
+    pub regex: String,
+
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ProfileArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only use these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TitleArgs {
+    /// Cleaned title of the show, ignoring case
+    pub title: String,
+
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Profile the show is tracked in
+    #[arg(short, long)]
+    pub profile: String,
+}
+
+#[derive(Args, Debug)]
+pub struct IgnoreArgs {
+    /// Cleaned title of the show, ignoring case
+    pub title: String,
+
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Profile the show is tracked in
+    #[arg(short, long)]
+    pub profile: String,
+
+    /// Notify about the show again
+    #[arg(short, long)]
+    pub undo: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only export these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// File to write the export to, stdout if not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Exported document to load
+    pub file: PathBuf,
+
+    /// Configuration file to use
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Only import these profiles of the configuration, all of them if not given
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+
+    /// Whether to merge with or replace the existing state
+    #[arg(short, long, value_enum, default_value_t)]
+    pub mode: ImportMode,
+}
+
+#[derive(Args, Debug)]
+pub struct CleardataArgs {
+    /// Only delete the state of these profiles
+    #[arg(short, long)]
+    pub profile: Vec<String>,
+}
@@ -0,0 +1,70 @@
+use std::{collections::HashSet, path::Path, sync::mpsc::{self, RecvTimeoutError}, time::Instant};
+
+use notify::{RecursiveMode, Watcher as _};
+
+use crate::watcher::Watcher;
+
+/// Show folder an event happened in, the first component below the watch folder
+fn show_folder(watch_folder: &Path, path: &Path) -> Option<String> {
+    let folder = path.strip_prefix(watch_folder).ok()?.components().next()?;
+    folder.as_os_str().to_str().map(|name| name.to_owned())
+}
+
+/// Keep running, re-scanning show folders as their contents change
+pub fn watch(watcher: &mut Watcher) {
+    let watch_folder = watcher.config().watch_folder().clone();
+    let config = watcher.config().watch().clone();
+
+    let (tx, rx) = mpsc::channel();
+    let mut fs_watcher = notify::recommended_watcher(tx).expect("Failed to create filesystem watcher");
+    fs_watcher
+        .watch(&watch_folder, RecursiveMode::Recursive)
+        .expect("Failed to watch folder");
+
+    println!("[{}] Watching {}", watcher.config().name(), watch_folder.display());
+
+    watcher.run();
+
+    let mut next_full_rescan = Instant::now() + config.full_rescan();
+    let mut dirty = HashSet::new();
+    let mut last_event = Instant::now();
+
+    loop {
+        let until_full_rescan = next_full_rescan.saturating_duration_since(Instant::now());
+
+        // Wait for the folders to settle before scanning them, copying a season fires lots of events
+        let timeout = if dirty.is_empty() {
+            until_full_rescan
+        } else {
+            config.debounce().saturating_sub(last_event.elapsed()).min(until_full_rescan)
+        };
+
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if !event.kind.is_access() {
+                    dirty.extend(event.paths.iter().filter_map(|path| show_folder(&watch_folder, path)));
+                    last_event = Instant::now();
+                }
+            },
+            Ok(Err(err)) => {
+                // Events may have been lost, e.g. the inotify queue overflowed
+                eprintln!("[{}] Filesystem watcher failed, rescanning everything: {err}", watcher.config().name());
+                next_full_rescan = Instant::now();
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => panic!("Filesystem watcher stopped!"),
+        }
+
+        if Instant::now() >= next_full_rescan {
+            watcher.run();
+
+            dirty.clear();
+            next_full_rescan = Instant::now() + config.full_rescan();
+        } else if !dirty.is_empty() && last_event.elapsed() >= config.debounce() {
+            let comparisons = watcher.update_folders(&dirty);
+            watcher.notify(comparisons);
+
+            dirty.clear();
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::{Arc, OnceLock};
+
+use tokio::{runtime::Runtime, sync::{mpsc, Semaphore}, task::JoinSet};
+
+use crate::{config::DeliveryConfig, discord::{Delivered, Discord, Job}, notify::NotifyError};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Runtime for the async delivery, driven from the blocking commands and shared by every profile
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start async runtime")
+    })
+}
+
+/// Deliver all jobs, several targets at once, keeping the order of the jobs within a target.
+///
+/// `on_done` runs on the calling task as soon as a job finished, so its state can be persisted right away.
+pub async fn deliver_all(discord: Arc<Discord>, config: &DeliveryConfig, jobs: Vec<Job>, mut on_done: impl FnMut(Job, Result<Delivered, NotifyError>)) {
+    let mut queues: Vec<(String, Vec<Job>)> = Vec::new();
+
+    for job in jobs {
+        match queues.iter_mut().find(|(target, _)| *target == job.target) {
+            Some((_, queue)) => queue.push(job),
+            None => queues.push((job.target.clone(), vec![job])),
+        }
+    }
+
+    let total = Arc::new(Semaphore::new(config.concurrency()));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut targets = JoinSet::new();
+
+    for (_, queue) in queues {
+        let per_target = Arc::new(Semaphore::new(config.per_target()));
+        let discord = discord.clone();
+        let total = total.clone();
+        let tx = tx.clone();
+
+        targets.spawn(async move {
+            let mut sends = JoinSet::new();
+
+            for job in queue {
+                // Permits are taken in queue order, so with one per target a job waits for the previous one
+                let target_permit = per_target.clone().acquire_owned().await.expect("Delivery semaphore closed");
+                let total_permit = total.clone().acquire_owned().await.expect("Delivery semaphore closed");
+                let discord = discord.clone();
+                let tx = tx.clone();
+
+                sends.spawn(async move {
+                    let result = discord.deliver(&job).await;
+                    drop((target_permit, total_permit));
+
+                    // The receiver only goes away once every sender is gone
+                    let _ = tx.send((job, result));
+                });
+            }
+
+            while let Some(joined) = sends.join_next().await {
+                joined.expect("Delivery task panicked");
+            }
+        });
+    }
+
+    drop(tx);
+
+    while let Some((job, result)) = rx.recv().await {
+        on_done(job, result);
+    }
+
+    while let Some(joined) = targets.join_next().await {
+        joined.expect("Delivery task panicked");
+    }
+}
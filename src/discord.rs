@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use reqwest::multipart::{Form, Part};
+
+use crate::{
+    artwork::{self, Artwork},
+    config::{ArtworkPlacement, Config},
+    mention::Mentions,
+    notify::NotifyError,
+    template::Notification,
+    watcher::Comparison,
+    webhook::{AllowedMentions, Attachment, Author, Embed, Field, Footer, Media, Webhook, WebhookMessage, FLAG_SUPPRESS_NOTIFICATIONS},
+};
+
+/// One notification for one Discord target, with the state left by earlier runs
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub target: String,
+    pub webhook_link: String,
+    pub notification: Notification,
+    /// Forum thread of the show in this target
+    pub thread_id: Option<String>,
+    /// Rolling message that is still young enough to be edited
+    pub rolling_message_id: Option<String>,
+}
+
+/// State to remember after a job was delivered
+#[derive(Debug, Default)]
+pub struct Delivered {
+    /// Forum thread created for the show
+    pub thread_id: Option<String>,
+    /// Newly posted rolling message
+    pub rolling_message_id: Option<String>,
+}
+
+/// Builds and sends Discord webhooks, without any per-show state
+#[derive(Clone, Debug)]
+pub struct Discord {
+    config: Config,
+    mentions: Mentions,
+    client: reqwest::Client,
+}
+
+impl Discord {
+    pub fn new(config: &Config, client: reqwest::Client) -> Discord {
+        Discord { config: config.clone(), mentions: Mentions::new(config), client }
+    }
+
+    /// Build the webhook for a notification, creating a forum post if `thread_name` is given
+    pub fn build(&self, notification: &Notification, thread_name: Option<String>) -> Result<(Webhook, Option<Artwork>), NotifyError> {
+        self.build_webhook(notification, thread_name, false)
+    }
+
+    /// Build the webhook for a made up notification, mentions are shown but nobody is pinged or notified
+    pub fn build_sample(&self, notification: &Notification) -> Result<(Webhook, Option<Artwork>), NotifyError> {
+        self.build_webhook(notification, None, true)
+    }
+
+    fn build_webhook(&self, notification: &Notification, thread_name: Option<String>, sample: bool) -> Result<(Webhook, Option<Artwork>), NotifyError> {
+        let mention = self.mentions.resolve(&notification.comparison);
+        let content = format!("{}{}", mention.prefix(), notification.content);
+
+        let fields = notification.fields
+            .iter()
+            .map(|field| Field::builder(field.name.clone(), field.value.clone()).inline(field.inline).build())
+            .collect::<Vec<Field>>();
+
+        let identity = self.config.identity();
+
+        let mut embed = Embed::builder()
+            .title(notification.title.clone())
+            .color(self.config.color_for(notification.comparison.kind()))
+            .fields(fields)
+            .timestamp(chrono::Utc::now());
+
+        if let Some(description) = &notification.description {
+            embed = embed.description(description.clone());
+        }
+
+        if let Some(footer) = identity.footer() {
+            let mut builder = Footer::builder(footer.text().to_owned());
+
+            if let Some(icon_url) = footer.icon_url() {
+                builder = builder.icon_url(icon_url.to_owned());
+            }
+
+            embed = embed.footer(builder.build());
+        }
+
+        let artwork = self.config.artwork().and_then(|config| {
+            let show = notification.comparison.show();
+            let path = self.config.watch_folder().join(show.folder_name()).join(show.artwork()?);
+
+            artwork::load(&path, config).map(|artwork| (artwork, config.placement()))
+        });
+
+        if let Some((artwork, placement)) = &artwork {
+            let media = Media::builder(format!("attachment://{}", artwork.file_name)).build();
+
+            embed = match placement {
+                ArtworkPlacement::Thumbnail => embed.thumbnail(media),
+                ArtworkPlacement::Image => embed.image(media),
+            };
+        }
+
+        if let Some(author) = identity.author() {
+            let mut builder = Author::builder(author.name().to_owned());
+
+            if let Some(url) = author.url() {
+                builder = builder.url(url.to_owned());
+            }
+
+            if let Some(icon_url) = author.icon_url() {
+                builder = builder.icon_url(icon_url.to_owned());
+            }
+
+            embed = embed.author(builder.build());
+        }
+
+        let mut webhook = Webhook::builder()
+            .content(content)
+            .username(identity.username().to_owned())
+            .embeds(vec![embed.build()]);
+
+        webhook = if sample {
+            webhook
+                .allowed_mentions(AllowedMentions::builder().build())
+                .flags(FLAG_SUPPRESS_NOTIFICATIONS)
+        } else {
+            webhook.allowed_mentions(mention.allowed_mentions())
+        };
+
+        if let Some(avatar_url) = identity.avatar_url() {
+            webhook = webhook.avatar_url(avatar_url.to_owned());
+        }
+
+        if let Some((artwork, _)) = &artwork {
+            webhook = webhook.attachments(vec![Attachment::builder(0, artwork.file_name.clone()).build()]);
+        }
+
+        if let Some(thread_name) = thread_name {
+            webhook = webhook.thread_name(thread_name);
+        }
+
+        let webhook = webhook.build()?;
+
+        Ok((webhook, artwork.map(|(artwork, _)| artwork)))
+    }
+
+    /// Send a webhook, anything but a success status is an error
+    pub async fn send(&self, method: reqwest::Method, url: reqwest::Url, webhook: &Webhook, artwork: Option<Artwork>) -> Result<reqwest::Response, NotifyError> {
+        let request = self.client.request(method, url);
+
+        let request = if let Some(artwork) = artwork {
+            let file = Part::bytes(artwork.bytes)
+                .file_name(artwork.file_name)
+                .mime_str(artwork.mime)
+                .expect("Failed to set artwork mime type");
+
+            let form = Form::new()
+                .text("payload_json", webhook.to_json())
+                .part("files[0]", file);
+
+            request.multipart(form)
+        } else {
+            request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(webhook.to_json())
+        };
+
+        let response = request.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(NotifyError::Status { status, body });
+        }
+
+        Ok(response)
+    }
+
+    /// Post a notification, editing the rolling message or creating a forum post where configured
+    pub async fn deliver(self: Arc<Self>, job: &Job) -> Result<Delivered, NotifyError> {
+        let title = job.notification.comparison.show().title();
+        let thread_id = job.thread_id.as_deref().filter(|_| self.config.forum_threads());
+
+        let missing_thread = self.config.forum_threads() && thread_id.is_none();
+
+        // Forum posts are created when a show is added, shows from before that need it enabled explicitly
+        let create_thread = missing_thread
+            && (matches!(job.notification.comparison, Comparison::NewlyAdded(_)) || self.config.forum_threads_for_existing());
+
+        if missing_thread && !create_thread {
+            return Err(NotifyError::NoThread(title.to_owned()));
+        }
+
+        let thread_name = create_thread.then(|| title.chars().take(100).collect());
+
+        // Reading and downscaling the artwork blocks
+        let discord = self.clone();
+        let notification = job.notification.clone();
+
+        let (webhook, artwork) = tokio::task::spawn_blocking(move || discord.build(&notification, thread_name))
+            .await
+            .expect("Building webhook panicked")?;
+        let rolling = self.config.rolling_message().is_some();
+
+        if let Some(id) = job.rolling_message_id.as_deref().filter(|_| rolling) {
+            let url = webhook_url(&job.webhook_link, Some(id), thread_id);
+
+            match self.send(reqwest::Method::PATCH, url, &webhook, artwork.clone()).await {
+                // The message was deleted in the meantime, post a new one instead
+                Err(NotifyError::Status { status: reqwest::StatusCode::NOT_FOUND, .. }) => {},
+                result => return result.map(|_| Delivered::default()),
+            }
+        }
+
+        // Only wait for the created message if we need its ids
+        let wait = rolling || create_thread;
+
+        let mut url = webhook_url(&job.webhook_link, None, thread_id);
+
+        if wait {
+            url.query_pairs_mut().append_pair("wait", "true");
+        }
+
+        let response = self.send(reqwest::Method::POST, url, &webhook, artwork).await?;
+
+        if !wait {
+            return Ok(Delivered::default());
+        }
+
+        let message: WebhookMessage = serde_json::from_str(&response.text().await?)
+            .map_err(|err| NotifyError::Response(err.to_string()))?;
+
+        Ok(Delivered {
+            // A forum post's thread has the same id as the channel its first message is in
+            thread_id: create_thread.then_some(message.channel_id),
+            rolling_message_id: rolling.then_some(message.id),
+        })
+    }
+}
+
+/// Webhook url, or the url of one of its messages, optionally inside a thread
+pub fn webhook_url(webhook_link: &str, message_id: Option<&str>, thread_id: Option<&str>) -> reqwest::Url {
+    let mut url = reqwest::Url::parse(webhook_link).expect("Failed to parse webhook link");
+
+    if let Some(thread_id) = thread_id {
+        url.query_pairs_mut().append_pair("thread_id", thread_id);
+    }
+
+    if let Some(id) = message_id {
+        url.path_segments_mut()
+            .expect("Webhook link cannot be a base")
+            .extend(["messages", id]);
+    }
+
+    url
+}
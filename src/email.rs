@@ -0,0 +1,193 @@
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+
+use crate::{config::{SmtpConfig, SmtpTls}, notify::NotifyError, template::Notification};
+
+#[derive(Clone)]
+pub struct Mailer {
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    digest: bool,
+    transport: SmtpTransport,
+}
+
+impl std::fmt::Debug for Mailer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mailer")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("digest", &self.digest)
+            .finish()
+    }
+}
+
+impl Mailer {
+    pub fn new(config: &SmtpConfig) -> Mailer {
+        let mut builder = match config.tls() {
+            SmtpTls::None => SmtpTransport::builder_dangerous(config.host()),
+            SmtpTls::StartTls => SmtpTransport::starttls_relay(config.host())
+                .expect("Failed to create STARTTLS transport"),
+            SmtpTls::Implicit => SmtpTransport::relay(config.host())
+                .expect("Failed to create TLS transport"),
+        };
+
+        if let Some(port) = config.port() {
+            builder = builder.port(port);
+        }
+
+        if let Some(username) = config.username() {
+            let password = config.password().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.to_owned(), password.to_owned()));
+        }
+
+        let from = config.from().parse().expect("Failed to parse smtp sender address");
+        let to = config.to()
+            .iter()
+            .map(|addr| addr.parse().expect("Failed to parse smtp recipient address"))
+            .collect::<Vec<Mailbox>>();
+
+        if to.is_empty() {
+            panic!("No smtp recipients given!");
+        }
+
+        Mailer {
+            from,
+            to,
+            digest: config.digest(),
+            transport: builder.build(),
+        }
+    }
+
+    /// Send the notifications of a run, either one email each or a single digest.
+    /// Every email is tried, a failed one doesn't keep the others from being sent.
+    pub fn send(&self, notifications: &[Notification]) -> Result<(), NotifyError> {
+        let emails = self.group(notifications);
+        let count = emails.len();
+        let mut failed = Vec::new();
+
+        for (subject, notifications) in emails {
+            if let Err(err) = self.send_message(subject.clone(), notifications) {
+                eprintln!("Failed to email {subject}: {err}");
+                failed.push(subject);
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(NotifyError::Email(format!("{} of {count} emails were not sent: {}", failed.len(), failed.join(", "))))
+        }
+    }
+
+    /// Subject and plain text body of every email `send` would send
+    pub fn preview(&self, notifications: &[Notification]) -> Vec<(String, String)> {
+        self.group(notifications)
+            .into_iter()
+            .map(|(subject, notifications)| (subject, self.render_text(notifications)))
+            .collect()
+    }
+
+    /// Split notifications into emails, one per notification or a single digest
+    fn group<'a>(&self, notifications: &'a [Notification]) -> Vec<(String, &'a [Notification])> {
+        if notifications.is_empty() {
+            return Vec::new();
+        }
+
+        if self.digest {
+            let subject = if notifications.len() > 1 {
+                format!("{} new changes", notifications.len())
+            } else {
+                notifications[0].title.clone()
+            };
+
+            vec![(subject, notifications)]
+        } else {
+            notifications
+                .iter()
+                .map(|notification| (notification.title.clone(), std::slice::from_ref(notification)))
+                .collect()
+        }
+    }
+
+    fn send_message(&self, subject: String, notifications: &[Notification]) -> Result<(), NotifyError> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject);
+
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(
+                self.render_text(notifications),
+                self.render_html(notifications),
+            ))
+            .map_err(|err| NotifyError::Email(err.to_string()))?;
+
+        self.transport.send(&message).map_err(|err| NotifyError::Email(err.to_string()))?;
+
+        Ok(())
+    }
+
+    fn render_text(&self, notifications: &[Notification]) -> String {
+        let mut text = String::new();
+
+        for notification in notifications {
+            text.push_str(&format!("{}\n{}\n", notification.content, notification.title));
+
+            if let Some(description) = &notification.description {
+                text.push_str(&format!("{description}\n"));
+            }
+
+            for field in &notification.fields {
+                text.push_str(&format!("{}: {}\n", field.name, field.value));
+            }
+
+            text.push('\n');
+        }
+
+        text
+    }
+
+    fn render_html(&self, notifications: &[Notification]) -> String {
+        let mut html = String::from("<html><body>");
+
+        for notification in notifications {
+            html.push_str(&format!(
+                "<p>{}</p><h3>{}</h3>",
+                escape_html(&notification.content),
+                escape_html(&notification.title)
+            ));
+
+            if let Some(description) = &notification.description {
+                html.push_str(&format!("<p>{}</p>", escape_html(description)));
+            }
+
+            html.push_str("<ul>");
+
+            for field in &notification.fields {
+                html.push_str(&format!(
+                    "<li><b>{}:</b> {}</li>",
+                    escape_html(&field.name),
+                    escape_html(&field.value)
+                ));
+            }
+
+            html.push_str("</ul>");
+        }
+
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,116 @@
+use std::{fs::File, io::BufReader, sync::Mutex};
+
+use atom_syndication::{Content, Entry, Feed, Generator, Link, Text};
+use chrono::{FixedOffset, Utc};
+
+use crate::{config::FeedConfig, email::escape_html, notify::NotifyError, state, template::Notification};
+
+/// Profiles running at the same time may share a feed, each has to see the entries of the others
+static FEED_LOCK: Mutex<()> = Mutex::new(());
+
+/// Prepend the notifications of a run to the Atom feed file, keeping at most `max_items` entries
+pub fn append(config: &FeedConfig, profile: &str, notifications: &[Notification], message_link: Option<&str>) -> Result<(), NotifyError> {
+    if notifications.is_empty() && config.path().exists() {
+        return Ok(());
+    }
+
+    let now = Utc::now().with_timezone(&FixedOffset::east_opt(0).expect("Failed to create UTC offset"));
+
+    let _guard = FEED_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut feed = read_feed(config, profile);
+
+    let mut entries = notifications
+        .iter()
+        .enumerate()
+        .map(|(i, notification)| {
+            let comp = &notification.comparison;
+            let mut entry = Entry::default();
+
+            entry.set_id(format!(
+                "urn:nav1-watcher:{}:{}:{}:{}-{}",
+                slug(profile),
+                comp.kind().as_str(),
+                slug(comp.show().title()),
+                now.timestamp(),
+                i
+            ));
+            entry.set_title(Text::plain(notification.title.clone()));
+            entry.set_summary(Some(Text::plain(notification.content.clone())));
+            entry.set_updated(now);
+            entry.set_published(Some(now));
+
+            let mut html = String::new();
+            if let Some(description) = &notification.description {
+                html.push_str(&format!("<p>{}</p>", escape_html(description)));
+            }
+            html.push_str("<ul>");
+            for field in &notification.fields {
+                html.push_str(&format!("<li><b>{}:</b> {}</li>", escape_html(&field.name), escape_html(&field.value)));
+            }
+            html.push_str("</ul>");
+
+            let mut content = Content::default();
+            content.set_content_type(Some(String::from("html")));
+            content.set_value(Some(html));
+            entry.set_content(Some(content));
+
+            if let Some(link) = message_link {
+                let mut entry_link = Link::default();
+                entry_link.set_href(link);
+                entry.set_links(vec![entry_link]);
+            }
+
+            entry
+        })
+        .collect::<Vec<Entry>>();
+
+    entries.extend(feed.entries().iter().cloned());
+    entries.truncate(config.max_items());
+
+    feed.set_entries(entries);
+    feed.set_updated(now);
+
+    if let Some(parent) = config.path().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Whoever serves the feed must never see a partially written one
+    let xml = feed.write_to(Vec::new()).map_err(std::io::Error::other)?;
+    state::write_atomic(config.path(), &xml)?;
+
+    Ok(())
+}
+
+fn read_feed(config: &FeedConfig, profile: &str) -> Feed {
+    if let Ok(file) = File::open(config.path()) {
+        match Feed::read_from(BufReader::new(file)) {
+            Ok(feed) => return feed,
+            Err(err) => eprintln!("Failed to read existing feed, starting a new one: {err}"),
+        }
+    }
+
+    let mut feed = Feed::default();
+    feed.set_id(format!("urn:nav1-watcher:{}", slug(profile)));
+    feed.set_title(Text::plain(
+        config.title().map(|t| t.to_owned()).unwrap_or_else(|| format!("nav1-watcher: {profile}"))
+    ));
+
+    let mut generator = Generator::default();
+    generator.set_value("nav1-watcher");
+    generator.set_version(Some(String::from(env!("CARGO_PKG_VERSION"))));
+    feed.set_generator(Some(generator));
+
+    if let Some(link) = config.link() {
+        let mut feed_link = Link::default();
+        feed_link.set_href(link);
+        feed.set_links(vec![feed_link]);
+    }
+
+    feed
+}
+
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect()
+}
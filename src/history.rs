@@ -0,0 +1,86 @@
+use chrono::{Local, TimeZone};
+
+use crate::{
+    config::Config,
+    state,
+    storage::{self, ChangeRecord},
+    watcher::Comparison,
+};
+
+fn format_time(at: i64) -> String {
+    Local.timestamp_opt(at, 0)
+        .single()
+        .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| at.to_string())
+}
+
+fn print_change(change: &ChangeRecord) {
+    println!("    {:<12} {}: {}", change.kind.as_str(), change.show, change.details);
+}
+
+/// List the kept runs of a profile and what changed in them, newest first
+pub fn history(config: &Config, limit: Option<usize>) {
+    let mut storage = storage::open_read_only(config, &state::data_dir());
+    let runs = storage.runs();
+
+    println!("Profile {}: {} runs", config.name(), runs.len());
+
+    for run in runs.iter().rev().take(limit.unwrap_or(usize::MAX)) {
+        println!("  #{:<5} {}  {} shows, {} changes", run.id, format_time(run.at), run.show_count, run.changes.len());
+
+        for change in &run.changes {
+            print_change(change);
+        }
+    }
+}
+
+/// Compare the snapshots of two runs, `to` defaults to the latest run
+pub fn diff(config: &Config, from: u64, to: Option<u64>) {
+    let mut storage = storage::open_read_only(config, &state::data_dir());
+
+    let to = to.unwrap_or_else(|| {
+        storage.runs()
+            .last()
+            .map(|run| run.id)
+            .expect("There are no runs yet!")
+    });
+
+    let mut snapshot = |run: u64| match storage.snapshot(run) {
+        Ok(Some(shows)) => shows,
+        Ok(None) => panic!("Run #{run} does not exist or was pruned!"),
+        Err(err) => panic!("Failed to read run #{run}: {err}"),
+    };
+
+    let old = snapshot(from);
+    let new = snapshot(to);
+
+    println!("Profile {}: run #{from} -> #{to}", config.name());
+
+    let mut titles = new.keys().chain(old.keys()).collect::<Vec<&String>>();
+    titles.sort();
+    titles.dedup();
+
+    let mut differences = 0;
+
+    for title in titles {
+        let comparison = match (old.get(title), new.get(title)) {
+            (Some(old), Some(new)) => new.compare(old).map(Comparison::Changed),
+            (None, Some(new)) => Some(Comparison::NewlyAdded(new.clone())),
+            (Some(_), None) => {
+                println!("    {:<12} {title}", "removed");
+                differences += 1;
+                continue;
+            },
+            (None, None) => None,
+        };
+
+        if let Some(comparison) = comparison {
+            print_change(&ChangeRecord::new(&comparison, 0));
+            differences += 1;
+        }
+    }
+
+    if differences == 0 {
+        println!("    No differences");
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+
+use crate::config::HttpConfig;
+
+/// Build the client shared by everything that talks HTTP
+pub fn client(config: &HttpConfig) -> Client {
+    let mut builder = Client::builder()
+        .user_agent(config.user_agent())
+        .timeout(Duration::from_secs(config.timeout_secs()))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs()))
+        .danger_accept_invalid_certs(config.accept_invalid_certs());
+
+    if let Some(proxy) = config.proxy() {
+        let proxy = Proxy::all(proxy)
+            .expect("Failed to parse http proxy")
+            .no_proxy(config.no_proxy().and_then(NoProxy::from_string));
+
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(ca_bundle) = config.ca_bundle() {
+        let pem = std::fs::read(ca_bundle).expect("Failed to read http ca_bundle");
+
+        for certificate in Certificate::from_pem_bundle(&pem).expect("Failed to parse http ca_bundle") {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().expect("Failed to build http client")
+}
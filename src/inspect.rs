@@ -0,0 +1,191 @@
+use clap::ValueEnum;
+use regex::RegexBuilder;
+use serde::Serialize;
+
+use crate::{config::Config, show::Show, state, storage};
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Serialize)]
+struct ShowSummary<'a> {
+    profile: &'a str,
+    title: &'a str,
+    folder_name: &'a str,
+    seasons: usize,
+    episodes: usize,
+}
+
+#[derive(Serialize)]
+struct SeasonDetails<'a> {
+    name: &'a str,
+    season_number: usize,
+    episodes: usize,
+}
+
+#[derive(Serialize)]
+struct ShowDetails<'a> {
+    profile: &'a str,
+    title: &'a str,
+    folder_name: &'a str,
+    tags: Vec<String>,
+    artwork: Option<&'a str>,
+    seasons: Vec<SeasonDetails<'a>>,
+}
+
+/// Persisted shows of every profile sorted by title, without rescanning
+fn load(configs: &[Config]) -> Vec<(&str, Show)> {
+    let data_dir = state::data_dir();
+    let mut shows = Vec::new();
+
+    for config in configs {
+        let mut storage = storage::open_read_only(config, &data_dir);
+        shows.extend(storage.load_shows().into_values().map(|show| (config.name(), show)));
+    }
+
+    shows.sort_by(|(a_profile, a), (b_profile, b)| a.title().cmp(b.title()).then(a_profile.cmp(b_profile)));
+    shows
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let widths = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| rows.iter().map(|row| row[i].chars().count()).chain([header.len()]).max().unwrap_or(0))
+        .collect::<Vec<usize>>();
+
+    let print_row = |cells: Vec<&str>| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<String>>()
+            .join("  ");
+
+        println!("{}", line.trim_end());
+    };
+
+    print_row(headers.to_vec());
+
+    for row in rows {
+        print_row(row.iter().map(|cell| cell.as_str()).collect());
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).expect("Failed to serialize output"));
+}
+
+fn print_summaries(shows: &[(&str, Show)], format: OutputFormat) {
+    let summaries = shows
+        .iter()
+        .map(|(profile, show)| ShowSummary {
+            profile,
+            title: show.title(),
+            folder_name: show.folder_name(),
+            seasons: show.season_count(),
+            episodes: show.episode_count(),
+        })
+        .collect::<Vec<ShowSummary>>();
+
+    match format {
+        OutputFormat::Json => print_json(&summaries),
+        OutputFormat::Table => {
+            let rows = summaries
+                .iter()
+                .map(|s| vec![s.profile.to_owned(), s.title.to_owned(), s.seasons.to_string(), s.episodes.to_string()])
+                .collect::<Vec<Vec<String>>>();
+
+            print_table(&["PROFILE", "TITLE", "SEASONS", "EPISODES"], &rows);
+        },
+    }
+}
+
+/// Every tracked show with its season and episode counts
+pub fn list(configs: &[Config], format: OutputFormat) {
+    print_summaries(&load(configs), format);
+}
+
+/// Tracked shows whose title matches a case-insensitive regex
+pub fn search(configs: &[Config], pattern: &str, format: OutputFormat) {
+    let re = RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .unwrap_or_else(|err| panic!("Invalid search regex {pattern:?}: {err}"));
+
+    let shows = load(configs)
+        .into_iter()
+        .filter(|(_, show)| re.is_match(show.title()))
+        .collect::<Vec<(&str, Show)>>();
+
+    print_summaries(&shows, format);
+}
+
+/// Per season breakdown of the shows with exactly this title, ignoring case
+pub fn show(configs: &[Config], title: &str, format: OutputFormat) {
+    let shows = load(configs)
+        .into_iter()
+        .filter(|(_, show)| show.title().to_lowercase() == title.to_lowercase())
+        .collect::<Vec<(&str, Show)>>();
+
+    if shows.is_empty() {
+        panic!("No show titled {title:?} is tracked!");
+    }
+
+    let details = shows
+        .iter()
+        .map(|(profile, show)| {
+            let mut seasons = show.seasons()
+                .iter()
+                .map(|season| SeasonDetails {
+                    name: season.name(),
+                    season_number: season.season_number(),
+                    episodes: season.episodes(),
+                })
+                .collect::<Vec<SeasonDetails>>();
+
+            // Seasons are stored in the order the directory listed them
+            seasons.sort_by_key(|season| (season.season_number, season.name));
+
+            ShowDetails {
+                profile,
+                title: show.title(),
+                folder_name: show.folder_name(),
+                tags: show.tags(),
+                artwork: show.artwork(),
+                seasons,
+            }
+        })
+        .collect::<Vec<ShowDetails>>();
+
+    match format {
+        OutputFormat::Json => print_json(&details),
+        OutputFormat::Table => {
+            for (i, show) in details.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+
+                println!("{} ({})", show.title, show.profile);
+                println!("Folder: {}", show.folder_name);
+
+                if !show.tags.is_empty() {
+                    println!("Tags: {}", show.tags.join(", "));
+                }
+
+                println!();
+
+                let rows = show.seasons
+                    .iter()
+                    .map(|season| vec![season.season_number.to_string(), season.name.to_owned(), season.episodes.to_string()])
+                    .collect::<Vec<Vec<String>>>();
+
+                print_table(&["SEASON", "NAME", "EPISODES"], &rows);
+            }
+        },
+    }
+}
@@ -1,21 +1,136 @@
+use std::{panic::AssertUnwindSafe, path::Path};
+
 use clap::Parser;
 use watcher::Watcher;
 
-use crate::cli::Cli;
+use crate::cli::{CheckArgs, Cli};
 
+mod artwork;
 mod config;
+mod daemon;
+mod delivery;
+mod discord;
+mod email;
+mod feed;
+mod history;
+mod http;
+mod inspect;
+mod mention;
+mod mqtt;
+mod notify;
+mod route;
+mod schedule;
+mod secret;
 mod cli;
 mod watcher;
 mod webhook;
 mod show;
+mod sqlite;
+mod state;
+mod storage;
+mod template;
+mod transfer;
+
+/// One watcher per selected profile, for the long running commands
+fn watchers(args: &CheckArgs) -> Vec<Watcher> {
+    config::load_profiles(&args.config, &args.profile)
+        .into_iter()
+        .map(|config| Watcher::new(config, args.no_webhook))
+        .collect()
+}
+
+/// Keep every profile running on its own thread. A profile that panics takes the whole process down,
+/// so a service manager notices instead of the profile silently stopping.
+fn run_profiles(watchers: Vec<Watcher>, run: fn(&mut Watcher)) {
+    std::thread::scope(|scope| {
+        for mut watcher in watchers {
+            scope.spawn(move || {
+                let name = watcher.config().name().to_owned();
+
+                if std::panic::catch_unwind(AssertUnwindSafe(|| run(&mut watcher))).is_err() {
+                    eprintln!("[{name}] Stopped after a panic, shutting down");
+                    std::process::exit(1);
+                }
+            });
+        }
+    });
+}
+
+/// Watcher of a single profile, for the commands that change one show
+fn profile_watcher(config_path: &Path, profile: &str) -> Watcher {
+    let config = config::load_profiles(config_path, &[profile.to_owned()])
+        .pop()
+        .expect("Profile was not loaded");
+
+    Watcher::new(config, false)
+}
 
 fn main() {
+    // Panic messages may contain webhook links or passwords
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("{}", secret::redact(&info.to_string()));
+    }));
+
     let cli = Cli::parse();
 
     match cli.command {
         cli::Commands::Check(args) => {
-            let mut watcher = Watcher::new(&args.config, args.no_webhook);
-            watcher.run();
+            for config in config::load_profiles(&args.config, &args.profile) {
+                let mut watcher = Watcher::new(config, args.no_webhook);
+                watcher.run();
+            }
+        },
+        cli::Commands::Watch(args) => {
+            run_profiles(watchers(&args), daemon::watch);
+        },
+        cli::Commands::Run(args) => {
+            run_profiles(watchers(&args), schedule::run);
+        },
+        cli::Commands::TestNotify(args) => {
+            for config in config::load_profiles(&args.config, &args.profile) {
+                println!("Profile {}:", config.name());
+
+                watcher::test_notify(&config, args.dry_run, args.thread_id.as_deref());
+            }
+        },
+        cli::Commands::History(args) => {
+            for config in config::load_profiles(&args.config, &args.profile) {
+                history::history(&config, args.limit);
+            }
+        },
+        cli::Commands::Diff(args) => {
+            for config in config::load_profiles(&args.config, &args.profile) {
+                history::diff(&config, args.from, args.to);
+            }
+        },
+        cli::Commands::List(args) => {
+            inspect::list(&config::load_profiles(&args.config, &args.profile), args.format);
+        },
+        cli::Commands::Show(args) => {
+            inspect::show(&config::load_profiles(&args.config, &args.profile), &args.title, args.format);
+        },
+        cli::Commands::Search(args) => {
+            inspect::search(&config::load_profiles(&args.config, &args.profile), &args.regex, args.format);
+        },
+        cli::Commands::Forget(args) => {
+            profile_watcher(&args.config, &args.profile).forget(&args.title);
+        },
+        cli::Commands::Ignore(args) => {
+            profile_watcher(&args.config, &args.profile).ignore(&args.title, args.undo);
+        },
+        cli::Commands::MarkSeen(args) => {
+            for config in config::load_profiles(&args.config, &args.profile) {
+                Watcher::new(config, true).mark_seen();
+            }
+        },
+        cli::Commands::Resend(args) => {
+            profile_watcher(&args.config, &args.profile).resend(&args.title);
+        },
+        cli::Commands::Export(args) => {
+            transfer::export(&config::load_profiles(&args.config, &args.profile), args.output.as_deref());
+        },
+        cli::Commands::Import(args) => {
+            transfer::import(&config::load_profiles(&args.config, &args.profile), &args.file, args.mode);
         },
         cli::Commands::Datadir => {
             if let Some(proj_dirs) = directories::ProjectDirs::from("xyz", "superyu", "nav1truenas") {
@@ -23,13 +138,16 @@ fn main() {
                 println!("Data directory: {}", data_dir.to_str().expect("Failed to convert pathbuf to string"));
             }
         },
-        cli::Commands::Cleardata => {
-            if let Some(proj_dirs) = directories::ProjectDirs::from("xyz", "superyu", "nav1truenas") {
-                let data_dir = proj_dirs.data_dir().to_path_buf();
-                std::fs::remove_dir_all(data_dir).expect("Could not delete data directory");
+        cli::Commands::Cleardata(args) => {
+            if args.profile.is_empty() {
+                std::fs::remove_dir_all(state::data_dir()).expect("Could not delete data directory");
                 println!("Removed data directory!");
             } else {
-                panic!("Could not get data directory")
+                for profile in &args.profile {
+                    config::check_profile_name(profile);
+                    state::clear_profile(&state::data_dir(), profile);
+                    println!("Removed data of profile {profile}!");
+                }
             }
         },
         
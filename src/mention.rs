@@ -0,0 +1,112 @@
+use regex::Regex;
+
+use crate::{config::{Config, MentionsConfig, Snowflake, Subscription}, watcher::Comparison, webhook::AllowedMentions};
+
+/// Roles and users to ping for a single change
+#[derive(Debug, Default)]
+pub struct Mention {
+    roles: Vec<Snowflake>,
+    users: Vec<Snowflake>,
+}
+
+impl Mention {
+    fn add(&mut self, roles: &[Snowflake], users: &[Snowflake]) {
+        for role in roles {
+            if !self.roles.contains(role) {
+                self.roles.push(role.clone());
+            }
+        }
+
+        for user in users {
+            if !self.users.contains(user) {
+                self.users.push(user.clone());
+            }
+        }
+    }
+
+    /// Mention prefix for the message content, empty if nobody is pinged
+    pub fn prefix(&self) -> String {
+        self.roles
+            .iter()
+            .map(|role| format!("<@&{role}> "))
+            .chain(self.users.iter().map(|user| format!("<@{user}> ")))
+            .collect()
+    }
+
+    /// Allow exactly these mentions, so nothing in titles can ping anyone else
+    pub fn allowed_mentions(&self) -> AllowedMentions {
+        let mut builder = AllowedMentions::builder();
+
+        for role in &self.roles {
+            builder = builder.role(role.to_string());
+        }
+
+        for user in &self.users {
+            builder = builder.user(user.to_string());
+        }
+
+        builder.build()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mentions {
+    role_ping_id: Option<Snowflake>,
+    config: MentionsConfig,
+    /// Compiled `title_regex` of every subscription, in the same order
+    regexes: Vec<Option<Regex>>,
+}
+
+impl Mentions {
+    pub fn new(config: &Config) -> Mentions {
+        let regexes = config.mentions()
+            .subscriptions()
+            .iter()
+            .map(|sub| {
+                sub.title_regex().map(|re| {
+                    Regex::new(re).unwrap_or_else(|err| panic!("Invalid mention title_regex {re:?}: {err}"))
+                })
+            })
+            .collect();
+
+        Mentions {
+            role_ping_id: config.role_ping_id().cloned(),
+            config: config.mentions().clone(),
+            regexes,
+        }
+    }
+
+    fn matches(sub: &Subscription, regex: Option<&Regex>, comp: &Comparison) -> bool {
+        let title = comp.show().title();
+
+        if let Some(kinds) = sub.kinds() {
+            if !kinds.contains(&comp.kind()) {
+                return false;
+            }
+        }
+
+        let show_matches = sub.show().is_some_and(|show| show.eq_ignore_ascii_case(title));
+        let regex_matches = regex.is_some_and(|re| re.is_match(title));
+
+        show_matches || regex_matches
+    }
+
+    pub fn resolve(&self, comp: &Comparison) -> Mention {
+        let mut mention = Mention::default();
+
+        if let Some(role) = &self.role_ping_id {
+            mention.add(std::slice::from_ref(role), &[]);
+        }
+
+        let targets = self.config.for_kind(comp.kind());
+        mention.add(targets.roles(), targets.users());
+
+        for (sub, regex) in self.config.subscriptions().iter().zip(&self.regexes) {
+            if Mentions::matches(sub, regex.as_ref(), comp) {
+                mention.add(sub.targets().roles(), sub.targets().users());
+            }
+        }
+
+        mention
+    }
+}
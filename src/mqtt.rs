@@ -0,0 +1,193 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, thread::JoinHandle, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use rumqttc::{Client, Event, MqttOptions, Outgoing, QoS};
+use serde::Serialize;
+
+use crate::{config::MqttConfig, notify::NotifyError, show::{Show, ShowComparison}, template::Notification, watcher::{ChangeKind, Comparison}};
+
+#[derive(Serialize)]
+struct ChangePayload<'a> {
+    kind: ChangeKind,
+    show: &'a str,
+    folder_name: &'a str,
+    title: String,
+    headline: String,
+    /// Season numbers that are new, empty unless kind is `new_seasons`
+    new_seasons: Vec<usize>,
+    new_episodes: usize,
+    seasons: usize,
+    episodes: usize,
+}
+
+#[derive(Serialize)]
+struct StatsPayload {
+    shows: usize,
+    seasons: usize,
+    episodes: usize,
+    /// Unix timestamp in seconds
+    updated_at: u64,
+}
+
+/// Broker connection of a profile, kept open and reconnected for as long as the watcher lives
+pub struct Mqtt {
+    client: Client,
+    connection: JoinHandle<Result<(), String>>,
+    /// Set once we disconnect, so a broker that is gone doesn't keep us waiting
+    closing: Arc<AtomicBool>,
+    topic_base: String,
+    qos: QoS,
+    retain: bool,
+}
+
+impl std::fmt::Debug for Mqtt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mqtt")
+            .field("topic_base", &self.topic_base)
+            .finish()
+    }
+}
+
+impl Mqtt {
+    pub fn connect(config: &MqttConfig, profile: &str) -> Mqtt {
+        let mut options = MqttOptions::new(config.client_id(profile), config.host(), config.port());
+        options.set_keep_alive(Duration::from_secs(30));
+
+        if let Some(username) = config.username() {
+            options.set_credentials(username, config.password().unwrap_or_default());
+        }
+
+        let qos = match config.qos() {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            other => unreachable!("Invalid mqtt qos {other} passed the config"),
+        };
+
+        let (client, mut connection) = Client::new(options, 10);
+        let closing = Arc::new(AtomicBool::new(false));
+
+        // The event loop has to be polled for anything to be sent, polling after an error reconnects.
+        // It stops once our disconnect went out.
+        let connection = {
+            let closing = closing.clone();
+            let profile = profile.to_owned();
+
+            std::thread::spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => return Ok(()),
+                        Ok(_) => {},
+                        Err(err) if closing.load(Ordering::SeqCst) => return Err(err.to_string()),
+                        Err(err) => {
+                            eprintln!("[{profile}] MQTT connection failed, reconnecting: {err}");
+                            std::thread::sleep(Duration::from_secs(5));
+                        },
+                    }
+                }
+
+                Ok(())
+            })
+        };
+
+        Mqtt {
+            client,
+            connection,
+            closing,
+            topic_base: topic_base(config, profile),
+            qos,
+            retain: config.retain(),
+        }
+    }
+
+    pub fn publish_change(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let (topic, payload) = change_payload(&self.topic_base, notification);
+
+        // Never block the watcher while the broker is away and the queue is full
+        self.client.try_publish(topic, self.qos, self.retain, payload).map_err(|err| NotifyError::Mqtt(err.to_string()))
+    }
+
+    /// Publish retained totals of the whole library
+    pub fn publish_stats<'a>(&self, shows: impl Iterator<Item = &'a Show>) -> Result<(), NotifyError> {
+        let stats = shows.fold(
+            StatsPayload { shows: 0, seasons: 0, episodes: 0, updated_at: 0 },
+            |mut stats, show| {
+                stats.shows += 1;
+                stats.seasons += show.season_count();
+                stats.episodes += show.episode_count();
+                stats
+            },
+        );
+
+        let stats = StatsPayload {
+            updated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time is before the unix epoch")
+                .as_secs(),
+            ..stats
+        };
+
+        let topic = format!("{}/stats", self.topic_base);
+        let payload = serde_json::to_vec(&stats).expect("Failed to serialize mqtt payload");
+
+        self.client.try_publish(topic, self.qos, true, payload).map_err(|err| NotifyError::Mqtt(err.to_string()))
+    }
+
+    /// Flush all queued messages and close the connection, gives up on the first connection error
+    pub fn disconnect(self) -> Result<(), NotifyError> {
+        self.closing.store(true, Ordering::SeqCst);
+
+        // Fails once the event loop stopped, its own error says why
+        let _ = self.client.disconnect();
+        self.connection.join().expect("MQTT connection thread panicked").map_err(NotifyError::Mqtt)
+    }
+}
+
+fn topic_base(config: &MqttConfig, profile: &str) -> String {
+    format!("{}/{}", config.topic_prefix(), topic_level(profile))
+}
+
+/// Topic and JSON payload of a change, without connecting to the broker
+pub fn change_message(config: &MqttConfig, profile: &str, notification: &Notification) -> (String, Vec<u8>) {
+    change_payload(&topic_base(config, profile), notification)
+}
+
+fn change_payload(topic_base: &str, notification: &Notification) -> (String, Vec<u8>) {
+    let comp = &notification.comparison;
+    let show = comp.show();
+
+    let (new_seasons, new_episodes) = match comp {
+        Comparison::NewlyAdded(show) => (Vec::new(), show.episode_count()),
+        Comparison::Changed(ShowComparison::NewSeasons(_, seasons)) => (
+            seasons.iter().map(|s| s.season_number()).collect(),
+            seasons.iter().fold(0usize, |c, s| c + s.episodes()),
+        ),
+        Comparison::Changed(ShowComparison::NewEpisodes(_, episodes)) => (Vec::new(), *episodes),
+    };
+
+    let payload = ChangePayload {
+        kind: comp.kind(),
+        show: show.title(),
+        folder_name: show.folder_name(),
+        title: notification.title.clone(),
+        headline: notification.content.clone(),
+        new_seasons,
+        new_episodes,
+        seasons: show.season_count(),
+        episodes: show.episode_count(),
+    };
+
+    let topic = format!("{topic_base}/{}/{}", topic_level(show.title()), comp.kind().as_str());
+    let payload = serde_json::to_vec(&payload).expect("Failed to serialize mqtt payload");
+
+    (topic, payload)
+}
+
+/// Turn a name into a single topic level, wildcards and separators are not allowed
+fn topic_level(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '+' | '#' => '_',
+            c => c,
+        })
+        .collect()
+}
@@ -0,0 +1,60 @@
+use std::fmt::Display;
+
+use crate::{secret, webhook::WebhookError};
+
+/// Failure to deliver a notification to one target
+#[derive(Debug)]
+pub enum NotifyError {
+    Webhook(WebhookError),
+    Request(reqwest::Error),
+    /// The server answered with a non-success status
+    Status { status: reqwest::StatusCode, body: String },
+    /// The server answered with a success status, but a body we don't understand
+    Response(String),
+    /// The show has no forum post to send its change to
+    NoThread(String),
+    /// A template failed to render the change
+    Template(String),
+    Email(String),
+    Mqtt(String),
+    Io(std::io::Error),
+}
+
+impl Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            NotifyError::Webhook(err) => format!("invalid webhook: {err}"),
+            NotifyError::Request(err) => format!("request failed: {err}"),
+            NotifyError::Status { status, body } => format!("{status}: {body}"),
+            NotifyError::Response(err) => format!("unexpected response: {err}"),
+            NotifyError::NoThread(show) => format!("{show} has no forum post, set forum_threads_for_existing to create one"),
+            NotifyError::Template(err) => format!("template failed: {err}"),
+            NotifyError::Email(err) => format!("email failed: {err}"),
+            NotifyError::Mqtt(err) => format!("mqtt failed: {err}"),
+            NotifyError::Io(err) => format!("io failed: {err}"),
+        };
+
+        f.write_str(&secret::redact(&message))
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+impl From<WebhookError> for NotifyError {
+    fn from(err: WebhookError) -> Self {
+        NotifyError::Webhook(err)
+    }
+}
+
+impl From<reqwest::Error> for NotifyError {
+    fn from(err: reqwest::Error) -> Self {
+        // The url contains the webhook token
+        NotifyError::Request(err.without_url())
+    }
+}
+
+impl From<std::io::Error> for NotifyError {
+    fn from(err: std::io::Error) -> Self {
+        NotifyError::Io(err)
+    }
+}
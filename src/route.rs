@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::{config::{Config, RouteRule}, watcher::Comparison};
+
+/// Name of the main `webhook_link` target
+pub const DISCORD: &str = "discord";
+pub const EMAIL: &str = "email";
+pub const MQTT: &str = "mqtt";
+pub const FEED: &str = "feed";
+
+#[derive(Clone, Debug)]
+pub struct Router {
+    watch_folder: PathBuf,
+    rules: Vec<RouteRule>,
+    /// Compiled `title_regex` of every rule, in the same order
+    regexes: Vec<Option<Regex>>,
+    /// Every configured target, used when there are no rules
+    all_targets: Vec<String>,
+}
+
+impl Router {
+    pub fn new(config: &Config) -> Router {
+        let mut all_targets = config.discord_targets()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<String>>();
+
+        for reserved in [EMAIL, MQTT, FEED] {
+            if all_targets.iter().any(|name| name == reserved) {
+                panic!("Webhook name {reserved:?} is reserved!");
+            }
+        }
+
+        if config.smtp().is_some() {
+            all_targets.push(String::from(EMAIL));
+        }
+
+        if config.mqtt().is_some() {
+            all_targets.push(String::from(MQTT));
+        }
+
+        if config.feed().is_some() {
+            all_targets.push(String::from(FEED));
+        }
+
+        for rule in config.routes() {
+            for target in rule.targets() {
+                if !all_targets.contains(target) {
+                    panic!("Route target {target:?} is not configured!");
+                }
+            }
+        }
+
+        let regexes = config.routes()
+            .iter()
+            .map(|rule| {
+                rule.title_regex().map(|re| {
+                    Regex::new(re).unwrap_or_else(|err| panic!("Invalid route title_regex {re:?}: {err}"))
+                })
+            })
+            .collect();
+
+        Router {
+            watch_folder: config.watch_folder().clone(),
+            rules: config.routes().to_vec(),
+            regexes,
+            all_targets,
+        }
+    }
+
+    fn matches(&self, rule: &RouteRule, regex: Option<&Regex>, comp: &Comparison) -> bool {
+        let show = comp.show();
+
+        if let Some(kinds) = rule.kinds() {
+            if !kinds.contains(&comp.kind()) {
+                return false;
+            }
+        }
+
+        if let Some(re) = regex {
+            if !re.is_match(show.title()) {
+                return false;
+            }
+        }
+
+        if let Some(tag) = rule.tag() {
+            let tag = tag.to_lowercase();
+
+            if !show.tags().iter().any(|t| t.to_lowercase().contains(&tag)) {
+                return false;
+            }
+        }
+
+        if let Some(folder) = rule.watch_folder() {
+            if folder != &self.watch_folder {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Names of the targets a change should be sent to
+    pub fn targets(&self, comp: &Comparison) -> Vec<String> {
+        if self.rules.is_empty() {
+            return self.all_targets.clone();
+        }
+
+        let mut targets = Vec::new();
+
+        for (rule, regex) in self.rules.iter().zip(&self.regexes) {
+            if self.matches(rule, regex.as_ref(), comp) {
+                for target in rule.targets() {
+                    if !targets.contains(target) {
+                        targets.push(target.clone());
+                    }
+                }
+            }
+        }
+
+        targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::show::{Show, ShowComparison};
+
+    fn router(routes: serde_json::Value) -> Router {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "webhook_link": "https://discord.com/api/webhooks/1/main",
+            "webhooks": { "uhd": "https://discord.com/api/webhooks/2/uhd" },
+            "watch_folder": "/shows",
+            "color": 0,
+            "routes": routes,
+        })).unwrap();
+
+        Router::new(&config)
+    }
+
+    fn new_show() -> Comparison {
+        Comparison::NewlyAdded(Show::sample())
+    }
+
+    fn new_episodes() -> Comparison {
+        Comparison::Changed(ShowComparison::NewEpisodes(Show::sample(), 1))
+    }
+
+    #[test]
+    fn everything_goes_everywhere_without_rules() {
+        assert_eq!(router(serde_json::json!([])).targets(&new_show()), vec!["discord", "uhd"]);
+    }
+
+    #[test]
+    fn rules_match_by_kind() {
+        let router = router(serde_json::json!([
+            { "kinds": ["new_show"], "targets": ["discord"] },
+            { "kinds": ["new_episodes"], "targets": ["uhd"] },
+        ]));
+
+        assert_eq!(router.targets(&new_show()), vec!["discord"]);
+        assert_eq!(router.targets(&new_episodes()), vec!["uhd"]);
+    }
+
+    #[test]
+    fn rules_match_by_title_and_tag() {
+        let router = router(serde_json::json!([
+            { "title_regex": "^Sample", "targets": ["discord"] },
+            { "tag": "1080P", "targets": ["uhd"] },
+            { "tag": "2160p", "targets": ["discord", "uhd"] },
+            { "title_regex": "Other", "targets": ["uhd"] },
+        ]));
+
+        assert_eq!(router.targets(&new_show()), vec!["discord", "uhd"]);
+    }
+
+    #[test]
+    fn rules_match_by_watch_folder() {
+        let router = router(serde_json::json!([
+            { "watch_folder": "/other", "targets": ["uhd"] },
+        ]));
+
+        assert!(router.targets(&new_show()).is_empty());
+    }
+
+    #[test]
+    fn targets_are_not_repeated() {
+        let router = router(serde_json::json!([
+            { "targets": ["uhd", "discord"] },
+            { "targets": ["discord"] },
+        ]));
+
+        assert_eq!(router.targets(&new_show()), vec!["uhd", "discord"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not configured")]
+    fn unknown_targets_are_rejected() {
+        router(serde_json::json!([{ "targets": ["email"] }]));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, str::FromStr, time::Instant};
+
+use chrono::{DateTime, Local};
+use rand::Rng;
+
+use crate::{config::ScheduleConfig, watcher::Watcher};
+
+enum Schedule {
+    Cron(Box<cron::Schedule>),
+    /// Interval counted from when the scheduler started
+    Interval { start: DateTime<Local>, interval: chrono::Duration },
+}
+
+impl Schedule {
+    fn new(config: &ScheduleConfig) -> Schedule {
+        match (config.cron(), config.interval()) {
+            (Some(expr), None) => {
+                let schedule = cron::Schedule::from_str(expr)
+                    .unwrap_or_else(|err| panic!("Invalid schedule cron {expr:?}: {err}"));
+
+                Schedule::Cron(Box::new(schedule))
+            },
+            (None, Some(interval)) => {
+                let interval = chrono::Duration::from_std(interval).expect("Schedule interval is too long");
+
+                if interval <= chrono::Duration::zero() {
+                    panic!("Schedule interval_minutes must be at least 1!");
+                }
+
+                Schedule::Interval { start: Local::now(), interval }
+            },
+            _ => panic!("Schedule needs exactly one of cron or interval_minutes!"),
+        }
+    }
+
+    /// First tick after `after`
+    fn next(&self, after: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Schedule::Cron(schedule) => schedule.after(&after).next().expect("Schedule cron never fires"),
+            Schedule::Interval { start, interval } => {
+                let elapsed = (after - *start).num_milliseconds().max(0);
+                let ticks = elapsed / interval.num_milliseconds() + 1;
+
+                *start + chrono::Duration::milliseconds(interval.num_milliseconds() * ticks)
+            },
+        }
+    }
+}
+
+/// Random delay of up to `max_secs`
+fn jitter(max_secs: u64) -> chrono::Duration {
+    chrono::Duration::seconds(rand::thread_rng().gen_range(0..=max_secs) as i64)
+}
+
+/// Check for changes on the configured schedule, forever
+pub fn run(watcher: &mut Watcher) {
+    let name = watcher.config().name().to_owned();
+
+    let config = watcher.config()
+        .schedule()
+        .cloned()
+        .unwrap_or_else(|| panic!("Profile {name:?} has no schedule configured!"));
+
+    let schedule = Schedule::new(&config);
+    let mut next = schedule.next(Local::now());
+
+    println!("[{name}] First run at {}", next.to_rfc3339());
+
+    loop {
+        let start_at = next + jitter(config.jitter_secs());
+
+        if let Ok(wait) = (start_at - Local::now()).to_std() {
+            std::thread::sleep(wait);
+        }
+
+        let started = Instant::now();
+        let comparisons = watcher.update_shows();
+
+        let mut kinds = BTreeMap::new();
+
+        for comp in &comparisons {
+            *kinds.entry(comp.kind().as_str()).or_insert(0) += 1;
+        }
+
+        let changes = comparisons.len();
+        let failed = watcher.notify(comparisons);
+
+        let kinds = kinds
+            .iter()
+            .map(|(kind, count)| format!(" {count} {kind}"))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        println!(
+            "[{name}] Run at {} finished in {:.1}s: {changes} changes{kinds}, {failed} failed deliveries",
+            start_at.to_rfc3339(),
+            started.elapsed().as_secs_f64(),
+        );
+
+        // Runs never overlap, ticks that passed while this one ran are skipped
+        let planned = schedule.next(next);
+        next = schedule.next(Local::now());
+
+        if next != planned {
+            eprintln!("[{name}] Run took too long, skipped the run at {}", planned.to_rfc3339());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn config(json: serde_json::Value) -> ScheduleConfig {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn cron_ticks() {
+        let schedule = Schedule::new(&config(serde_json::json!({ "cron": "0 */15 * * * *" })));
+
+        assert_eq!(schedule.next(at(10, 0, 0)), at(10, 15, 0));
+        assert_eq!(schedule.next(at(10, 7, 30)), at(10, 15, 0));
+        assert_eq!(schedule.next(at(10, 59, 59)), at(11, 0, 0));
+    }
+
+    #[test]
+    fn interval_ticks_from_start() {
+        let schedule = Schedule::Interval { start: at(10, 0, 0), interval: chrono::Duration::minutes(20) };
+
+        assert_eq!(schedule.next(at(10, 0, 0)), at(10, 20, 0));
+        assert_eq!(schedule.next(at(10, 19, 59)), at(10, 20, 0));
+        assert_eq!(schedule.next(at(10, 20, 0)), at(10, 40, 0));
+        // Ticks missed while a run took long are skipped, not caught up
+        assert_eq!(schedule.next(at(11, 5, 0)), at(11, 20, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "exactly one")]
+    fn cron_and_interval_are_exclusive() {
+        Schedule::new(&config(serde_json::json!({ "cron": "0 * * * * *", "interval_minutes": 5 })));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn interval_must_not_be_zero() {
+        Schedule::new(&config(serde_json::json!({ "interval_minutes": 0 })));
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        assert_eq!(jitter(0), chrono::Duration::zero());
+
+        for _ in 0..100 {
+            let jitter = jitter(5);
+            assert!(jitter >= chrono::Duration::zero() && jitter <= chrono::Duration::seconds(5));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Every secret value read so far, redacted from errors and logs
+static KNOWN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Where a secret comes from, as written in the config
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Source {
+    Plain(String),
+    Env { env: String },
+    File { file: PathBuf },
+}
+
+/// Config value that may be read from an environment variable or a file,
+/// e.g. `{"env": "NAV1_WEBHOOK"}` or `{"file": "/run/secrets/webhook"}`
+#[derive(Clone)]
+pub struct Secret {
+    source: Source,
+    value: String,
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = Source::deserialize(deserializer)?;
+
+        let value = match &source {
+            Source::Plain(value) => value.clone(),
+            Source::Env { env } => std::env::var(env)
+                .map_err(|err| serde::de::Error::custom(format!("failed to read secret from env var {env}: {err}")))?,
+            Source::File { file } => std::fs::read_to_string(file)
+                .map_err(|err| serde::de::Error::custom(format!("failed to read secret from {}: {err}", file.display())))?
+                .trim_end()
+                .to_owned(),
+        };
+
+        register(&value);
+
+        Ok(Secret { source, value })
+    }
+}
+
+/// Serializes where the secret comes from, plain values are redacted
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.source {
+            Source::Plain(_) => serializer.serialize_str("[redacted]"),
+            source => source.serialize(serializer),
+        }
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+fn register(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    let mut known = KNOWN.lock().expect("Secret registry poisoned");
+    known.push(value.to_owned());
+
+    // The token of a Discord webhook link is its last path segment
+    if let Ok(url) = reqwest::Url::parse(value) {
+        if let Some(token) = url.path_segments().and_then(|mut s| s.next_back()) {
+            if token.len() >= 16 {
+                known.push(token.to_owned());
+            }
+        }
+    }
+
+    // Replace longer secrets first so a token inside a link doesn't leave the rest behind,
+    // equal ones have to end up next to each other to be deduplicated
+    known.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    known.dedup();
+}
+
+/// Replace every known secret in a message
+pub fn redact(message: &str) -> String {
+    let known = KNOWN.lock().expect("Secret registry poisoned");
+
+    known.iter().fold(message.to_owned(), |message, secret| message.replace(secret.as_str(), "[redacted]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_registered_once() {
+        for value in ["secret-aaaa", "secret-bbbb", "secret-aaaa", "secret-bbbb"] {
+            register(value);
+        }
+
+        let known = KNOWN.lock().unwrap();
+        assert_eq!(known.iter().filter(|secret| secret.starts_with("secret-")).count(), 2);
+    }
+
+    #[test]
+    fn webhook_token_is_redacted_on_its_own() {
+        register("https://discord.com/api/webhooks/1/abcdefghijklmnopqrstuvwxyz");
+
+        assert_eq!(redact("token abcdefghijklmnopqrstuvwxyz leaked"), "token [redacted] leaked");
+        assert_eq!(redact("url https://discord.com/api/webhooks/1/abcdefghijklmnopqrstuvwxyz"), "url [redacted]");
+    }
+}
@@ -1,24 +1,81 @@
 #![allow(dead_code)]
 
-use std::{ffi::OsString, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
 use serde::{Deserialize, Serialize};
 
+use crate::artwork;
+
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct Show {
-    folder_name: OsString,
+    folder_name: String,
     title: String,
     seasons: Vec<Season>,
+    /// File name of the poster inside the show folder
+    #[serde(default)]
+    artwork: Option<String>,
+    /// Discord forum thread the show is announced in, per target name
+    #[serde(default)]
+    threads: BTreeMap<String, String>,
 }
 
 impl Show {
+    /// Rebuild a show that was persisted field by field
+    pub fn from_parts(folder_name: String, title: String, seasons: Vec<Season>, artwork: Option<String>, threads: BTreeMap<String, String>) -> Show {
+        Show {
+            folder_name,
+            title,
+            seasons,
+            artwork,
+            threads,
+        }
+    }
+
     pub fn title<'a>(&'a self) -> &'a str {
         &self.title
     }
 
     pub fn folder_name<'a>(&'a self) -> &'a str {
-        self.folder_name.to_str().expect("Failed to get &str")
+        &self.folder_name
+    }
+
+    /// Bracketed tags of the folder name, e.g. `nAV1-1080p-HDR`
+    pub fn tags(&self) -> Vec<String> {
+        let re = regex::Regex::new(r"\[([^\]]+)\]").unwrap();
+
+        re.captures_iter(self.folder_name())
+            .map(|c| c[1].to_owned())
+            .collect()
+    }
+
+    pub fn artwork(&self) -> Option<&str> {
+        self.artwork.as_deref()
+    }
+
+    pub fn thread_id(&self, target: &str) -> Option<&str> {
+        self.threads.get(target).map(|id| id.as_str())
+    }
+
+    pub fn threads(&self) -> &BTreeMap<String, String> {
+        &self.threads
+    }
+
+    pub fn set_thread_id(&mut self, target: &str, thread_id: String) {
+        self.threads.insert(target.to_owned(), thread_id);
+    }
+
+    /// Carry over state that is not read from disk from the persisted Show object
+    pub fn keep_state(&mut self, other: &Show) {
+        self.threads = other.threads.clone();
+    }
+
+    pub fn seasons(&self) -> &[Season] {
+        &self.seasons
+    }
+
+    pub fn season_count(&self) -> usize {
+        self.seasons.len()
     }
 
     pub fn episode_count(&self) -> usize {
@@ -115,39 +172,55 @@ impl Show {
         None
     }
 
-    pub fn from_folder(path: &Path) -> Show {
+    /// Made up show that does not exist on disk
+    pub fn sample() -> Show {
+        Show {
+            folder_name: String::from("Sample Show [nAV1-1080p]"),
+            title: String::from("Sample Show"),
+            seasons: vec![Season::sample(1, 12), Season::sample(2, 10)],
+            artwork: None,
+            threads: BTreeMap::new(),
+        }
+    }
+
+    /// Scan a show folder, fails if it can't be read, e.g. because it was moved away
+    pub fn from_folder(path: &Path) -> std::io::Result<Show> {
         let folder_name = path
             .file_name()
             .expect("Failed to get show folder name")
+            .to_str()
+            .expect("Failed to get &str from title")
             .to_owned();
 
-        let title = Show::clean_title(
-            folder_name.to_str().expect("Failed to get &str from title")
-        );
-
-        let seasons = path.read_dir().expect("Failed to readdir show folder")
-            .map(|f| f.ok())
-            .fold(Vec::new(), |mut vec, f| {
-                if let Some(entry) = f {
-                    if entry.file_type().expect("Failed to get file type when fetching seasons").is_dir() {
-                        if let Some(folder_name) = entry.file_name().to_str() {
-                            if Season::is_season(folder_name) {
-                                vec.push(
-                                    Season::from_folder(&entry.path())
-                                );
-                            }
-                        }
-                    }
+        let title = Show::clean_title(&folder_name);
+
+        let mut seasons = Vec::new();
+
+        for entry in path.read_dir()?.flatten() {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+
+            if is_dir && entry.file_name().to_str().is_some_and(Season::is_season) {
+                match Season::from_folder(&entry.path()) {
+                    Ok(season) => seasons.push(season),
+                    // The season was moved away while the show was scanned
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+                    Err(err) => return Err(err),
                 }
+            }
+        }
 
-                vec
-            });
+        let artwork = artwork::CANDIDATES
+            .iter()
+            .find(|candidate| path.join(candidate).is_file())
+            .map(|candidate| String::from(*candidate));
 
-        Show {
+        Ok(Show {
             folder_name,
             title,
             seasons,
-        }
+            artwork,
+            threads: BTreeMap::new(),
+        })
     }
 
     fn clean_title<'a>(title: &'a str) -> String {
@@ -164,10 +237,13 @@ impl Show {
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct Season {
-    folder_name: OsString,
+    folder_name: String,
     name: String,
     season_number: usize,
     episodes: usize,
+    /// File names of the episodes, empty in state from before they were tracked
+    #[serde(default)]
+    episode_files: Vec<String>,
 }
 
 impl Season {
@@ -176,49 +252,62 @@ impl Season {
         re.captures(folder_name.as_bytes()).is_some()
     }
 
-    pub fn from_folder(path: &Path) -> Season {
+    /// Rebuild a season that was persisted field by field
+    pub fn from_parts(folder_name: String, name: String, season_number: usize, episodes: usize, episode_files: Vec<String>) -> Season {
+        Season { folder_name, name, season_number, episodes, episode_files }
+    }
+
+    fn sample(season_number: usize, episodes: usize) -> Season {
+        let name = format!("Season {season_number}");
+        let episode_files = (1..=episodes).map(|episode| format!("S{season_number:02}E{episode:02}.mkv")).collect();
+
+        Season { folder_name: name.clone(), name, season_number, episodes, episode_files }
+    }
+
+    pub fn from_folder(path: &Path) -> std::io::Result<Season> {
         let folder_name = path
             .file_name()
             .expect("Failed to get season folder name")
-            .to_owned();
-
-        let name = folder_name
             .to_str()
             .expect("Failed to get &str from folder name")
             .to_owned();
 
+        let name = folder_name.clone();
+
         let season_number = name
             .clone()
             .split_off(7)
             .parse::<usize>()
             .expect("Failed to parse season number");
     
-        let episodes = path.read_dir().expect("Failed to readdir season folder")
-            .map(|f| f.ok())
-            .fold(0usize, |mut counter, f| {
-
-                if let Some(entry) = f {
-                    let is_file = entry.file_type().expect("Failed to get file type when fetching episodes").is_file();
+        let mut episode_files = path.read_dir()?
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|file_name| file_name.ends_with(".mkv"))
+            .collect::<Vec<String>>();
 
-                    if is_file && entry.file_name().to_string_lossy().ends_with(".mkv") {
-                        counter += 1;
-                    }
-                }
+        episode_files.sort();
 
-                counter
-            });
-
-        Season { folder_name, name, season_number, episodes }
+        Ok(Season { folder_name, name, season_number, episodes: episode_files.len(), episode_files })
     }
 
     pub fn folder_name<'a>(&'a self) -> &'a str {
-        self.folder_name.to_str().expect("Failed to get &str")
+        &self.folder_name
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     pub fn episodes(&self) -> usize {
         self.episodes
     }
 
+    pub fn episode_files(&self) -> &[String] {
+        &self.episode_files
+    }
+
     pub fn season_number(&self) -> usize {
         self.season_number
     }
@@ -0,0 +1,602 @@
+use std::{collections::{BTreeMap, BTreeSet}, path::Path};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use serde_json::Value;
+
+use crate::{
+    show::{Season, Show},
+    state::{self, StateKind},
+    storage::{self, ChangeRecord, Messages, NotificationRecord, PostedMessage, Run, Shows, Storage, StoredSnapshot},
+};
+
+/// Schema of the database, `PRAGMA user_version` is the number of migrations applied
+const MIGRATIONS: [&str; 1] = [
+    "
+    CREATE TABLE shows (
+        title TEXT PRIMARY KEY,
+        folder_name TEXT NOT NULL,
+        artwork TEXT
+    );
+
+    CREATE TABLE seasons (
+        show_title TEXT NOT NULL REFERENCES shows (title) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        folder_name TEXT NOT NULL,
+        name TEXT NOT NULL,
+        season_number INTEGER NOT NULL,
+        episodes INTEGER NOT NULL,
+        PRIMARY KEY (show_title, position)
+    );
+
+    CREATE TABLE episodes (
+        show_title TEXT NOT NULL,
+        season_position INTEGER NOT NULL,
+        file_name TEXT NOT NULL,
+        PRIMARY KEY (show_title, season_position, file_name),
+        FOREIGN KEY (show_title, season_position) REFERENCES seasons (show_title, position) ON DELETE CASCADE
+    );
+
+    CREATE TABLE threads (
+        show_title TEXT NOT NULL REFERENCES shows (title) ON DELETE CASCADE,
+        target TEXT NOT NULL,
+        thread_id TEXT NOT NULL,
+        PRIMARY KEY (show_title, target)
+    );
+
+    CREATE TABLE messages (
+        target TEXT NOT NULL,
+        show_title TEXT NOT NULL,
+        message_id TEXT NOT NULL,
+        posted_at INTEGER NOT NULL,
+        PRIMARY KEY (target, show_title)
+    );
+
+    CREATE TABLE ignored (
+        show_title TEXT PRIMARY KEY
+    );
+
+    -- Ids are never reused, the snapshot of a pruned run can still store shows of later ones
+    CREATE TABLE runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        at INTEGER NOT NULL,
+        show_count INTEGER NOT NULL
+    );
+
+    CREATE TABLE snapshots (
+        run INTEGER PRIMARY KEY,
+        -- Schema version of the JSON state files the snapshot was serialized with
+        schema_version INTEGER NOT NULL,
+        snapshot TEXT NOT NULL
+    );
+
+    CREATE TABLE changes (
+        id INTEGER PRIMARY KEY,
+        run INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+        at INTEGER NOT NULL,
+        show_title TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        details TEXT NOT NULL
+    );
+
+    CREATE INDEX changes_run ON changes (run);
+    CREATE INDEX changes_show_title ON changes (show_title);
+
+    CREATE TABLE notifications (
+        id INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        target TEXT NOT NULL,
+        show_title TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        error TEXT
+    );
+
+    CREATE INDEX notifications_show_title ON notifications (show_title);
+    ",
+];
+
+/// State of a profile in a SQLite database
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> SqliteStorage {
+        let mut connection = Connection::open(path).expect("Failed to open state database");
+
+        connection.pragma_update(None, "journal_mode", "WAL").expect("Failed to enable WAL");
+        connection.pragma_update(None, "foreign_keys", true).expect("Failed to enable foreign keys");
+
+        SqliteStorage::migrate(&mut connection);
+
+        SqliteStorage { connection }
+    }
+
+    /// Open the database without ever writing to it, a missing one is empty
+    pub fn open_read_only(path: &Path) -> SqliteStorage {
+        if !path.exists() {
+            let mut connection = Connection::open_in_memory().expect("Failed to open state database");
+            SqliteStorage::migrate(&mut connection);
+
+            return SqliteStorage { connection };
+        }
+
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .expect("Failed to open state database");
+
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .expect("Failed to read database schema version");
+
+        if version != MIGRATIONS.len() {
+            panic!("State database {} has schema version {version} instead of {}, run check once to migrate it!", path.display(), MIGRATIONS.len());
+        }
+
+        SqliteStorage { connection }
+    }
+
+    fn migrate(connection: &mut Connection) {
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .expect("Failed to read database schema version");
+
+        if version > MIGRATIONS.len() {
+            panic!("State database schema version {version} was written by a newer nav1-watcher, this one supports up to {}!", MIGRATIONS.len());
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = connection.transaction().expect("Failed to start migration");
+
+            tx.execute_batch(migration).expect("Failed to migrate state database");
+            tx.pragma_update(None, "user_version", i + 1).expect("Failed to update database schema version");
+            tx.commit().expect("Failed to commit migration");
+        }
+    }
+
+    fn insert_show(tx: &Transaction, show: &Show) -> rusqlite::Result<()> {
+        tx.execute(
+            "INSERT INTO shows (title, folder_name, artwork) VALUES (?1, ?2, ?3)",
+            params![show.title(), show.folder_name(), show.artwork()],
+        )?;
+
+        for (position, season) in show.seasons().iter().enumerate() {
+            tx.execute(
+                "INSERT INTO seasons (show_title, position, folder_name, name, season_number, episodes) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![show.title(), position, season.folder_name(), season.name(), season.season_number(), season.episodes()],
+            )?;
+
+            for file_name in season.episode_files() {
+                tx.execute(
+                    "INSERT INTO episodes (show_title, season_position, file_name) VALUES (?1, ?2, ?3)",
+                    params![show.title(), position, file_name],
+                )?;
+            }
+        }
+
+        for (target, thread_id) in show.threads() {
+            tx.execute(
+                "INSERT INTO threads (show_title, target, thread_id) VALUES (?1, ?2, ?3)",
+                params![show.title(), target, thread_id],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn query_shows(&self) -> rusqlite::Result<Shows> {
+        let mut episodes: BTreeMap<(String, usize), Vec<String>> = BTreeMap::new();
+
+        let mut statement = self.connection.prepare(
+            "SELECT show_title, season_position, file_name FROM episodes ORDER BY show_title, season_position, file_name",
+        )?;
+
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            episodes.entry((row.get(0)?, row.get(1)?)).or_default().push(row.get(2)?);
+        }
+
+        let mut seasons: BTreeMap<String, Vec<Season>> = BTreeMap::new();
+
+        let mut statement = self.connection.prepare(
+            "SELECT show_title, position, folder_name, name, season_number, episodes FROM seasons ORDER BY show_title, position",
+        )?;
+
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let show_title: String = row.get(0)?;
+            let files = episodes.remove(&(show_title.clone(), row.get(1)?)).unwrap_or_default();
+
+            let season = Season::from_parts(row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, files);
+            seasons.entry(show_title).or_default().push(season);
+        }
+
+        let mut threads: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+        let mut statement = self.connection.prepare("SELECT show_title, target, thread_id FROM threads")?;
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            threads.entry(row.get(0)?).or_default().insert(row.get(1)?, row.get(2)?);
+        }
+
+        let mut shows = Shows::new();
+
+        let mut statement = self.connection.prepare("SELECT title, folder_name, artwork FROM shows")?;
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let title: String = row.get(0)?;
+
+            let show = Show::from_parts(
+                row.get(1)?,
+                title.clone(),
+                seasons.remove(&title).unwrap_or_default(),
+                row.get(2)?,
+                threads.remove(&title).unwrap_or_default(),
+            );
+
+            shows.insert(title, show);
+        }
+
+        Ok(shows)
+    }
+
+    fn query_messages(&self) -> rusqlite::Result<Messages> {
+        let mut messages = Messages::new();
+
+        let mut statement = self.connection.prepare("SELECT target, show_title, message_id, posted_at FROM messages")?;
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let message = PostedMessage { id: row.get(2)?, posted_at: row.get(3)? };
+
+            messages
+                .entry(row.get(0)?)
+                .or_default()
+                .insert(row.get(1)?, message);
+        }
+
+        Ok(messages)
+    }
+
+    fn write_shows(&mut self, shows: &Shows) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        // Seasons, their episodes and threads are removed along with their show
+        tx.execute("DELETE FROM shows", [])?;
+
+        for show in shows.values() {
+            SqliteStorage::insert_show(&tx, show)?;
+        }
+
+        tx.commit()
+    }
+
+    fn write_show(&mut self, show: Option<&Show>, title: &str) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        tx.execute("DELETE FROM shows WHERE title = ?1", params![title])?;
+
+        if let Some(show) = show {
+            SqliteStorage::insert_show(&tx, show)?;
+        }
+
+        tx.commit()
+    }
+
+    fn write_messages(&mut self, messages: &Messages) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        tx.execute("DELETE FROM messages", [])?;
+
+        for (target, messages) in messages {
+            for (title, message) in messages {
+                tx.execute(
+                    "INSERT INTO messages (target, show_title, message_id, posted_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![target, title, message.id, message.posted_at],
+                )?;
+            }
+        }
+
+        tx.commit()
+    }
+
+    fn query_ignored(&self) -> rusqlite::Result<BTreeSet<String>> {
+        let mut statement = self.connection.prepare("SELECT show_title FROM ignored")?;
+        let titles = statement.query_map([], |row| row.get(0))?;
+
+        titles.collect()
+    }
+
+    fn write_ignored(&mut self, ignored: &BTreeSet<String>) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        tx.execute("DELETE FROM ignored", [])?;
+
+        for title in ignored {
+            tx.execute("INSERT INTO ignored (show_title) VALUES (?1)", params![title])?;
+        }
+
+        tx.commit()
+    }
+
+    fn insert_run(&mut self, at: i64, shows: &Shows, changes: &[ChangeRecord]) -> rusqlite::Result<u64> {
+        let runs = self.query_runs()?;
+        let latest = storage::latest_snapshot(&runs, |run| self.stored(run));
+        let tx = self.connection.transaction()?;
+
+        tx.execute("INSERT INTO runs (at, show_count) VALUES (?1, ?2)", params![at, shows.len()])?;
+
+        let run = tx.last_insert_rowid();
+        let snapshot = StoredSnapshot::new(run as u64, shows, latest.as_ref());
+
+        tx.execute(
+            "INSERT INTO snapshots (run, schema_version, snapshot) VALUES (?1, ?2, ?3)",
+            params![run, state::SCHEMA_VERSION, serde_json::to_string(&snapshot).expect("Failed to serialize snapshot")],
+        )?;
+
+        for change in changes {
+            tx.execute(
+                "INSERT INTO changes (run, at, show_title, kind, details) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![run, change.at, change.show, change.kind.as_str(), change.details],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(run as u64)
+    }
+
+    fn query_runs(&self) -> rusqlite::Result<Vec<Run>> {
+        let mut changes: BTreeMap<u64, Vec<ChangeRecord>> = BTreeMap::new();
+
+        let mut statement = self.connection.prepare(
+            "SELECT run, at, show_title, kind, details FROM changes ORDER BY id",
+        )?;
+
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(3)?;
+
+            let change = ChangeRecord {
+                at: row.get(1)?,
+                show: row.get(2)?,
+                kind: serde_json::from_value(Value::String(kind)).expect("Unknown change kind in state database"),
+                details: row.get(4)?,
+            };
+
+            changes.entry(row.get(0)?).or_default().push(change);
+        }
+
+        let mut statement = self.connection.prepare("SELECT id, at, show_count FROM runs ORDER BY at, id")?;
+        let mut rows = statement.query([])?;
+        let mut runs = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let id = row.get(0)?;
+
+            runs.push(Run {
+                id,
+                at: row.get(1)?,
+                show_count: row.get(2)?,
+                changes: changes.remove(&id).unwrap_or_default(),
+            });
+        }
+
+        Ok(runs)
+    }
+
+    /// What a run stores, migrated to the current schema
+    fn stored(&self, run: u64) -> Result<Option<StoredSnapshot>, String> {
+        let stored: Option<(u32, String)> = self.connection
+            .query_row("SELECT schema_version, snapshot FROM snapshots WHERE run = ?1", params![run], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()
+            .map_err(|err| err.to_string())?;
+
+        let Some((version, snapshot)) = stored else {
+            return Ok(None);
+        };
+
+        let parsed = serde_json::from_str(&snapshot)
+            .map_err(|err| err.to_string())
+            .and_then(|value| state::migrate_data(StateKind::Snapshot, version, value))
+            .and_then(|value| serde_json::from_value(value).map_err(|err| err.to_string()));
+
+        parsed.map(Some).map_err(|err| format!("Snapshot of run #{run} is unreadable: {err}"))
+    }
+
+    fn query_snapshot(&self, run: u64) -> Result<Option<Shows>, String> {
+        let exists = self.connection
+            .query_row("SELECT 1 FROM runs WHERE id = ?1", params![run], |_| Ok(()))
+            .optional()
+            .map_err(|err| err.to_string())?;
+
+        // Pruned runs keep their snapshot while later runs still need shows from it
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        let Some(snapshot) = self.stored(run)? else {
+            return Ok(None);
+        };
+
+        snapshot.assemble(run, |run| self.stored(run)).map(Some)
+    }
+
+    fn remove_runs(&mut self, runs: &[Run]) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        // Their changes are removed along with them
+        for run in runs {
+            tx.execute("DELETE FROM runs WHERE id = ?1", params![run.id])?;
+        }
+
+        tx.commit()?;
+
+        let needed = match storage::needed_snapshots(&self.query_runs()?, |run| self.stored(run)) {
+            Ok(needed) => needed,
+            Err(err) => {
+                // Keep everything rather than losing what the unreadable one needs
+                eprintln!("Not removing pruned history: {err}");
+                return Ok(());
+            },
+        };
+
+        let mut statement = self.connection.prepare("SELECT run FROM snapshots")?;
+        let stored = statement.query_map([], |row| row.get::<_, u64>(0))?.collect::<rusqlite::Result<Vec<u64>>>()?;
+        drop(statement);
+
+        for run in stored.into_iter().filter(|run| !needed.contains(run)) {
+            self.connection.execute("DELETE FROM snapshots WHERE run = ?1", params![run])?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_notifications(&mut self, records: &[NotificationRecord]) -> rusqlite::Result<()> {
+        let tx = self.connection.transaction()?;
+
+        for record in records {
+            tx.execute(
+                "INSERT INTO notifications (at, target, show_title, kind, error) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![record.at, record.target, record.show, record.kind.as_str(), record.error],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    fn query_notifications(&self) -> rusqlite::Result<Vec<NotificationRecord>> {
+        let mut statement = self.connection.prepare("SELECT at, target, show_title, kind, error FROM notifications ORDER BY id")?;
+        let mut rows = statement.query([])?;
+        let mut records = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(3)?;
+
+            records.push(NotificationRecord {
+                at: row.get(0)?,
+                target: row.get(1)?,
+                show: row.get(2)?,
+                kind: serde_json::from_value(Value::String(kind)).expect("Unknown change kind in state database"),
+                error: row.get(4)?,
+            });
+        }
+
+        Ok(records)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load_shows(&mut self) -> Shows {
+        self.query_shows().expect("Failed to read shows from state database")
+    }
+
+    fn save_shows(&mut self, shows: &Shows) {
+        self.write_shows(shows).expect("Failed to write shows to state database");
+    }
+
+    fn save_show(&mut self, shows: &Shows, title: &str) {
+        self.write_show(shows.get(title), title).expect("Failed to write show to state database");
+    }
+
+    fn load_messages(&mut self) -> Messages {
+        self.query_messages().expect("Failed to read messages from state database")
+    }
+
+    fn save_messages(&mut self, messages: &Messages) {
+        self.write_messages(messages).expect("Failed to write messages to state database");
+    }
+
+    fn load_ignored(&mut self) -> BTreeSet<String> {
+        self.query_ignored().expect("Failed to read ignored shows from state database")
+    }
+
+    fn save_ignored(&mut self, ignored: &BTreeSet<String>) {
+        self.write_ignored(ignored).expect("Failed to write ignored shows to state database");
+    }
+
+    fn record_run(&mut self, at: i64, shows: &Shows, changes: &[ChangeRecord]) -> u64 {
+        self.insert_run(at, shows, changes).expect("Failed to write run to state database")
+    }
+
+    fn runs(&mut self) -> Vec<Run> {
+        self.query_runs().expect("Failed to read runs from state database")
+    }
+
+    fn snapshot(&mut self, run: u64) -> Result<Option<Shows>, String> {
+        self.query_snapshot(run)
+    }
+
+    fn delete_runs(&mut self, runs: &[Run]) {
+        self.remove_runs(runs).expect("Failed to delete runs from state database");
+    }
+
+    fn log_notifications(&mut self, records: &[NotificationRecord]) {
+        self.insert_notifications(records).expect("Failed to write notification log to state database");
+    }
+
+    fn notifications(&mut self) -> Vec<NotificationRecord> {
+        self.query_notifications().expect("Failed to read notification log from state database")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_round_trip() {
+        let mut storage = SqliteStorage::open(Path::new(":memory:"));
+
+        let mut show = Show::sample();
+        show.set_thread_id("discord", String::from("123"));
+
+        let shows = Shows::from([(show.title().to_owned(), show)]);
+        storage.save_shows(&shows);
+
+        let loaded = storage.load_shows();
+
+        assert_eq!(loaded, shows);
+        assert_eq!(loaded["Sample Show"].seasons()[0].episode_files().len(), 12);
+    }
+
+    #[test]
+    fn deleting_a_show_deletes_its_episodes() {
+        let mut storage = SqliteStorage::open(Path::new(":memory:"));
+        let show = Show::sample();
+
+        storage.save_shows(&Shows::from([(show.title().to_owned(), show)]));
+        storage.save_shows(&Shows::new());
+
+        let episodes: usize = storage.connection.query_row("SELECT COUNT(*) FROM episodes", [], |row| row.get(0)).unwrap();
+        assert_eq!(episodes, 0);
+    }
+
+    #[test]
+    fn pruned_runs_keep_the_shows_later_ones_need() {
+        let mut storage = SqliteStorage::open(Path::new(":memory:"));
+
+        let show = Show::sample();
+        let mut changed = show.clone();
+        changed.set_thread_id("discord", String::from("123"));
+
+        let first = Shows::from([(String::from("A"), show.clone()), (String::from("B"), show.clone())]);
+        let second = Shows::from([(String::from("A"), show), (String::from("B"), changed)]);
+
+        let run = storage.record_run(1, &first, &[]);
+        let next = storage.record_run(2, &second, &[]);
+
+        let runs = storage.runs();
+        storage.delete_runs(&runs[..1]);
+
+        let snapshots: usize = storage.connection.query_row("SELECT COUNT(*) FROM snapshots", [], |row| row.get(0)).unwrap();
+
+        assert_eq!(snapshots, 2);
+        assert_eq!(storage.snapshot(run).unwrap(), None);
+        assert_eq!(storage.snapshot(next).unwrap(), Some(second));
+    }
+}
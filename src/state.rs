@@ -0,0 +1,382 @@
+use std::{fs::{File, OpenOptions, TryLockError}, io::Write, path::{Path, PathBuf}};
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Directory all profiles keep their state in
+pub fn data_dir() -> PathBuf {
+    directories::ProjectDirs::from("xyz", "superyu", "nav1truenas")
+        .expect("Could not get data directory!")
+        .data_dir()
+        .to_path_buf()
+}
+
+/// Files and directories a profile keeps in the data directory, besides its lock file
+const PROFILE_FILES: [&str; 22] = [
+    "json", "json.bak", "json.tmp",
+    "messages.json", "messages.json.bak", "messages.json.tmp",
+    "ignored.json", "ignored.json.bak", "ignored.json.tmp",
+    "runs.jsonl", "runs.jsonl.bak", "runs.jsonl.tmp",
+    "notifications.jsonl",
+    "history",
+    "sqlite3", "sqlite3-wal", "sqlite3-shm", "sqlite3-journal",
+    "sqlite3.tmp", "sqlite3.tmp-wal", "sqlite3.tmp-shm",
+    "lock",
+];
+
+/// Delete all state of one profile, unless another process is using it
+pub fn clear_profile(data_dir: &Path, profile: &str) {
+    let lock = ProfileLock::acquire(data_dir, profile);
+
+    for suffix in PROFILE_FILES {
+        let path = data_dir.join(format!("{profile}.{suffix}"));
+
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+
+        match result {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => panic!("Failed to remove {}: {err}", path.display()),
+            _ => {},
+        }
+    }
+
+    drop(lock);
+}
+
+/// Advisory lock on a profile's state, released when dropped
+#[derive(Debug)]
+pub struct ProfileLock {
+    _file: File,
+}
+
+impl ProfileLock {
+    /// Lock a profile, so two runs can't overwrite each other's state
+    pub fn acquire(data_dir: &Path, profile: &str) -> ProfileLock {
+        std::fs::create_dir_all(data_dir).expect("Failed to create data directory");
+
+        let path = data_dir.join(format!("{profile}.lock"));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .expect("Failed to open lock file");
+
+        match file.try_lock() {
+            Ok(()) => ProfileLock { _file: file },
+            Err(TryLockError::WouldBlock) => panic!("Profile {profile:?} is already in use by another nav1-watcher process!"),
+            Err(TryLockError::Error(err)) => panic!("Failed to lock {}: {err}", path.display()),
+        }
+    }
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Previous version of a state file, kept in case the current one is unreadable
+fn backup_path(path: &Path) -> PathBuf {
+    with_extension(path, "bak")
+}
+
+/// Replace a file without ever leaving a partially written one behind, the previous one is kept as backup
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp_path = with_extension(path, "tmp");
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(contents)?;
+    tmp.sync_all()?;
+
+    if path.exists() {
+        std::fs::rename(path, backup_path(path))?;
+    }
+
+    std::fs::rename(&tmp_path, path)?;
+
+    // The renames are only durable once the directory is
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()
+}
+
+/// Current layout of the state files, bump it and add a migration when it changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What a state file contains, migrations differ between them
+#[derive(Clone, Copy, Debug)]
+pub enum StateKind {
+    /// Shows of a profile, keyed by CLEANED title
+    Shows,
+    /// Rolling messages, keyed by target name and CLEANED title
+    Messages,
+    /// CLEANED titles that are never notified about
+    Ignored,
+    /// Shows a run stores itself along with the runs that store the others, see `storage::StoredSnapshot`
+    Snapshot,
+}
+
+/// Upgrades the data of a state file by one schema version, index 0 upgrades from version 0 to 1.
+/// Version 0 is the bare data without an envelope, its shows kept folder names as serde's `OsString`.
+const MIGRATIONS: [fn(StateKind, Value) -> Value; 1] = [
+    |kind, mut data| {
+        if let (StateKind::Shows, Some(shows)) = (kind, data.as_object_mut()) {
+            for show in shows.values_mut().filter_map(Value::as_object_mut) {
+                plain_name(show.get_mut("folder_name"));
+                plain_name(show.get_mut("artwork"));
+
+                for season in show.get_mut("seasons").and_then(Value::as_array_mut).into_iter().flatten() {
+                    plain_name(season.get_mut("folder_name"));
+                }
+            }
+        }
+
+        data
+    },
+];
+
+/// Turn a serialized `OsString` into a plain string, replacing what isn't valid UTF-8
+fn plain_name(name: Option<&mut Value>) {
+    let Some(name) = name else {
+        return;
+    };
+
+    let lossy = match name {
+        Value::Object(os_string) => match os_string.iter().next() {
+            Some((platform, Value::Array(units))) => {
+                let units = units.iter().filter_map(Value::as_u64);
+
+                match platform.as_str() {
+                    "Unix" => String::from_utf8_lossy(&units.map(|unit| unit as u8).collect::<Vec<u8>>()).into_owned(),
+                    "Windows" => String::from_utf16_lossy(&units.map(|unit| unit as u16).collect::<Vec<u16>>()),
+                    _ => return,
+                }
+            },
+            _ => return,
+        },
+        _ => return,
+    };
+
+    if lossy.contains(char::REPLACEMENT_CHARACTER) {
+        eprintln!("Folder name {lossy:?} is not valid UTF-8, it is kept with the invalid parts replaced");
+    }
+
+    *name = Value::String(lossy);
+}
+
+/// Header written around the data of every state file
+#[derive(Debug, Deserialize, Serialize)]
+struct Envelope<T> {
+    schema_version: u32,
+    tool_version: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    data: T,
+}
+
+/// A versioned state file of one profile
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    kind: StateKind,
+    created_at: DateTime<Utc>,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf, kind: StateKind) -> StateFile {
+        StateFile { path, kind, created_at: Utc::now() }
+    }
+
+    /// Read the state file, migrating it to the current schema.
+    /// Falls back to its backup if it is missing or unreadable, `None` if neither exists.
+    pub fn load<T: DeserializeOwned>(&mut self) -> Option<T> {
+        // Starting over would announce every show as new
+        self.try_load().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like `load`, but an unreadable file without a readable backup is an error
+    pub fn try_load<T: DeserializeOwned>(&mut self) -> Result<Option<T>, String> {
+        let backup = backup_path(&self.path);
+
+        let read = match StateFile::read(&self.path, self.kind) {
+            Some(Ok(read)) => read,
+            Some(Err(err)) => {
+                eprintln!("{} is unreadable, trying its backup: {err}", self.path.display());
+                StateFile::read_backup(&self.path, &backup, self.kind)?
+            },
+            None if backup.exists() => {
+                eprintln!("{} is missing, trying its backup", self.path.display());
+                StateFile::read_backup(&self.path, &backup, self.kind)?
+            },
+            None => return Ok(None),
+        };
+
+        let (created_at, data) = read;
+        self.created_at = created_at;
+
+        Ok(Some(data))
+    }
+
+    fn read_backup<T: DeserializeOwned>(path: &Path, backup: &Path, kind: StateKind) -> Result<(DateTime<Utc>, T), String> {
+        match StateFile::read(backup, kind) {
+            Some(Ok(read)) => Ok(read),
+            Some(Err(err)) => Err(format!("Backup {} is unreadable too: {err}", backup.display())),
+            None => Err(format!("{} is unreadable and there is no backup!", path.display())),
+        }
+    }
+
+    fn read<T: DeserializeOwned>(path: &Path, kind: StateKind) -> Option<Result<(DateTime<Utc>, T), String>> {
+        let bytes = std::fs::read(path).ok()?;
+
+        Some(serde_json::from_slice(&bytes)
+            .map_err(|err| err.to_string())
+            .and_then(|value| migrate(kind, value))
+            .and_then(|envelope| {
+                let data = serde_json::from_value(envelope.data).map_err(|err| err.to_string())?;
+                Ok((envelope.created_at, data))
+            }))
+    }
+
+    pub fn save<T: Serialize>(&self, data: &T) -> std::io::Result<()> {
+        let envelope = Envelope {
+            schema_version: SCHEMA_VERSION,
+            tool_version: String::from(env!("CARGO_PKG_VERSION")),
+            created_at: self.created_at,
+            updated_at: Utc::now(),
+            data,
+        };
+
+        let json = serde_json::to_vec(&envelope).expect("Failed to serialize state");
+        write_atomic(&self.path, &json)
+    }
+
+    /// Whether the state file or its backup exists
+    pub fn exists(&self) -> bool {
+        self.path.exists() || backup_path(&self.path).exists()
+    }
+
+    /// Delete the state file along with its backup
+    pub fn remove(&self) -> std::io::Result<()> {
+        for path in [self.path.clone(), backup_path(&self.path)] {
+            match std::fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Bring a state file of any earlier schema version to the current one
+fn migrate(kind: StateKind, value: Value) -> Result<Envelope<Value>, String> {
+    let mut envelope = match value.get("schema_version") {
+        Some(_) => serde_json::from_value::<Envelope<Value>>(value).map_err(|err| err.to_string())?,
+        None => Envelope {
+            schema_version: 0,
+            tool_version: String::from("unknown"),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            data: value,
+        },
+    };
+
+    envelope.data = migrate_data(kind, envelope.schema_version, envelope.data)?;
+    envelope.schema_version = SCHEMA_VERSION;
+
+    Ok(envelope)
+}
+
+/// Upgrade data written with an earlier schema version to the current one
+pub fn migrate_data(kind: StateKind, version: u32, mut data: Value) -> Result<Value, String> {
+    let version = version as usize;
+
+    if version > MIGRATIONS.len() {
+        return Err(format!("schema version {version} was written by a newer nav1-watcher, this one supports up to {SCHEMA_VERSION}"));
+    }
+
+    for migration in &MIGRATIONS[version..] {
+        data = migration(kind, data);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Empty directory of its own for every test
+    pub fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nav1-watcher-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn current_data_is_left_alone() {
+        let current = json!({ "uhd": { "Show": { "id": "1", "posted_at": 0 } } });
+
+        assert_eq!(migrate_data(StateKind::Messages, 0, current.clone()).unwrap(), current);
+        assert_eq!(migrate_data(StateKind::Messages, SCHEMA_VERSION, current.clone()).unwrap(), current);
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        assert!(migrate_data(StateKind::Shows, SCHEMA_VERSION + 1, json!({})).is_err());
+    }
+
+    #[test]
+    fn unversioned_file_is_wrapped() {
+        let dir = test_dir("unversioned");
+        let path = dir.join("t.messages.json");
+        std::fs::write(&path, r#"{"Show":{"id":"1","posted_at":0}}"#).unwrap();
+
+        let loaded: Value = StateFile::new(path, StateKind::Messages).load().unwrap();
+
+        assert_eq!(loaded, json!({ "Show": { "id": "1", "posted_at": 0 } }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn os_string_folder_names_become_strings() {
+        let shows = json!({
+            "Show": {
+                "folder_name": { "Unix": [83, 104, 111, 119] },
+                "title": "Show",
+                "seasons": [{ "folder_name": { "Windows": [83, 49] }, "name": "S1", "season_number": 1, "episodes": 0 }],
+                "artwork": null,
+            },
+        });
+
+        let migrated = migrate_data(StateKind::Shows, 0, shows).unwrap();
+
+        assert_eq!(migrated["Show"]["folder_name"], "Show");
+        assert_eq!(migrated["Show"]["seasons"][0]["folder_name"], "S1");
+        assert_eq!(migrated["Show"]["artwork"], Value::Null);
+    }
+
+    #[test]
+    fn corrupt_file_falls_back_to_backup() {
+        let dir = test_dir("backup");
+        let path = dir.join("t.ignored.json");
+        let file = StateFile::new(path.clone(), StateKind::Ignored);
+
+        file.save(&vec!["first"]).unwrap();
+        file.save(&vec!["second"]).unwrap();
+        std::fs::write(&path, "{\"schema_ver").unwrap();
+
+        let loaded: Vec<String> = StateFile::new(path, StateKind::Ignored).load().unwrap();
+
+        assert_eq!(loaded, vec!["first"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
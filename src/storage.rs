@@ -0,0 +1,592 @@
+use std::{collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap}, fs::OpenOptions, io::Write, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, HistoryConfig, StorageBackend},
+    show::{Show, ShowComparison},
+    sqlite::SqliteStorage,
+    state::{self, StateFile, StateKind},
+    template::Notification,
+    watcher::{ChangeKind, Comparison},
+};
+
+/// Shows keyed by CLEANED title
+pub type Shows = HashMap<String, Show>;
+
+/// Rolling Discord messages, keyed by target name and CLEANED title
+pub type Messages = HashMap<String, HashMap<String, PostedMessage>>;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostedMessage {
+    pub id: String,
+    /// Unix timestamp in seconds
+    pub posted_at: i64,
+}
+
+/// A change that was detected, kept as history
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChangeRecord {
+    /// Unix timestamp in seconds
+    pub at: i64,
+    pub show: String,
+    pub kind: ChangeKind,
+    pub details: String,
+}
+
+impl ChangeRecord {
+    pub fn new(comp: &Comparison, at: i64) -> ChangeRecord {
+        let details = match comp {
+            Comparison::NewlyAdded(show) => format!("{} seasons, {} episodes", show.season_count(), show.episode_count()),
+            Comparison::Changed(ShowComparison::NewSeasons(_, seasons)) => seasons
+                .iter()
+                .map(|season| season.name().to_owned())
+                .collect::<Vec<String>>()
+                .join(", "),
+            Comparison::Changed(ShowComparison::NewEpisodes(_, count)) => format!("{count} new episodes"),
+        };
+
+        ChangeRecord {
+            at,
+            show: comp.show().title().to_owned(),
+            kind: comp.kind(),
+            details,
+        }
+    }
+}
+
+/// A scan that changed the shows, with a snapshot of them kept as history
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Run {
+    pub id: u64,
+    /// Unix timestamp in seconds
+    pub at: i64,
+    pub show_count: usize,
+    pub changes: Vec<ChangeRecord>,
+}
+
+/// The shows of a run, a show that didn't change is only stored by the first run that had it like this
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StoredSnapshot {
+    /// Shows that are new or changed since the run before
+    pub shows: Shows,
+    /// Every show of the snapshot with the run that stores it, this one or an earlier one
+    pub stored_in: BTreeMap<String, u64>,
+}
+
+impl StoredSnapshot {
+    /// Store only the shows of `run` that differ from the `previous` snapshot and where it stores them
+    pub fn new(run: u64, shows: &Shows, previous: Option<&(Shows, BTreeMap<String, u64>)>) -> StoredSnapshot {
+        let mut snapshot = StoredSnapshot::default();
+
+        for (title, show) in shows {
+            let unchanged = previous.and_then(|(previous, stored_in)| {
+                stored_in.get(title).filter(|_| previous.get(title) == Some(show))
+            });
+
+            match unchanged {
+                Some(stored_in) => {
+                    snapshot.stored_in.insert(title.clone(), *stored_in);
+                },
+                None => {
+                    snapshot.shows.insert(title.clone(), show.clone());
+                    snapshot.stored_in.insert(title.clone(), run);
+                },
+            }
+        }
+
+        snapshot
+    }
+
+    /// Put the shows of `run` back together, `stored` reads what another run stores
+    pub fn assemble(self, run: u64, mut stored: impl FnMut(u64) -> Result<Option<StoredSnapshot>, String>) -> Result<Shows, String> {
+        let mut by_run = BTreeMap::from([(run, self.shows)]);
+        let mut shows = Shows::new();
+
+        for (title, stored_in) in self.stored_in {
+            if let Entry::Vacant(entry) = by_run.entry(stored_in) {
+                let snapshot = stored(stored_in)?.ok_or_else(|| format!("the shows stored by run #{stored_in} are gone"))?;
+                entry.insert(snapshot.shows);
+            }
+
+            let show = by_run[&stored_in]
+                .get(&title)
+                .cloned()
+                .ok_or_else(|| format!("run #{stored_in} does not store {title}"))?;
+
+            shows.insert(title, show);
+        }
+
+        Ok(shows)
+    }
+}
+
+/// Snapshot of the latest run along with where it stores its shows, new runs only store what differs from it
+pub fn latest_snapshot(runs: &[Run], mut stored: impl FnMut(u64) -> Result<Option<StoredSnapshot>, String>) -> Option<(Shows, BTreeMap<String, u64>)> {
+    let run = runs.last()?.id;
+    let snapshot = stored(run).ok()??;
+    let stored_in = snapshot.stored_in.clone();
+
+    // Without it the new run stores all of its shows itself
+    let shows = snapshot.assemble(run, stored).ok()?;
+
+    Some((shows, stored_in))
+}
+
+/// Runs whose stored shows are still needed by the given runs, including the runs themselves
+pub fn needed_snapshots(runs: &[Run], mut stored: impl FnMut(u64) -> Result<Option<StoredSnapshot>, String>) -> Result<BTreeSet<u64>, String> {
+    let mut needed = BTreeSet::new();
+
+    for run in runs {
+        needed.insert(run.id);
+
+        if let Some(snapshot) = stored(run.id)? {
+            needed.extend(snapshot.stored_in.into_values());
+        }
+    }
+
+    Ok(needed)
+}
+
+/// Outcome of sending a change to one target
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NotificationRecord {
+    /// Unix timestamp in seconds
+    pub at: i64,
+    pub target: String,
+    pub show: String,
+    pub kind: ChangeKind,
+    /// Why the delivery failed, `None` if it succeeded
+    pub error: Option<String>,
+}
+
+impl NotificationRecord {
+    pub fn new(target: &str, notification: &Notification, error: Option<String>, at: i64) -> NotificationRecord {
+        NotificationRecord {
+            at,
+            target: target.to_owned(),
+            show: notification.comparison.show().title().to_owned(),
+            kind: notification.comparison.kind(),
+            error,
+        }
+    }
+}
+
+/// Where a profile keeps its state, failing to persist it panics like every other state write
+pub trait Storage: std::fmt::Debug + Send {
+    fn load_shows(&mut self) -> Shows;
+
+    fn save_shows(&mut self, shows: &Shows);
+
+    /// Persist a single show that changed, by default by saving all of them
+    fn save_show(&mut self, shows: &Shows, _title: &str) {
+        self.save_shows(shows);
+    }
+
+    fn load_messages(&mut self) -> Messages;
+
+    fn save_messages(&mut self, messages: &Messages);
+
+    /// CLEANED titles that are tracked but never notified about
+    fn load_ignored(&mut self) -> BTreeSet<String>;
+
+    fn save_ignored(&mut self, ignored: &BTreeSet<String>);
+
+    /// Keep a snapshot of the shows after a scan that changed them, returns the id of the run.
+    /// Shows that are the same as in the latest run are not stored again.
+    fn record_run(&mut self, at: i64, shows: &Shows, changes: &[ChangeRecord]) -> u64;
+
+    /// Every kept run, oldest first by `at`, runs that were imported may have higher ids than newer ones
+    fn runs(&mut self) -> Vec<Run>;
+
+    /// The shows as they were after a run, `None` if the run does not exist
+    fn snapshot(&mut self, run: u64) -> Result<Option<Shows>, String>;
+
+    /// Drop runs along with their changes, and the shows no other run needs
+    fn delete_runs(&mut self, runs: &[Run]);
+
+    /// Drop runs older than the retention allows
+    fn prune_runs(&mut self, retention: &HistoryConfig) {
+        let (_, pruned) = split_retention(retention, self.runs());
+
+        if !pruned.is_empty() {
+            self.delete_runs(&pruned);
+        }
+    }
+
+    fn log_notifications(&mut self, records: &[NotificationRecord]);
+
+    /// The notification log, oldest first
+    fn notifications(&mut self) -> Vec<NotificationRecord>;
+}
+
+/// Runs to keep and runs to drop, the newest run is always kept
+pub fn split_retention(retention: &HistoryConfig, mut runs: Vec<Run>) -> (Vec<Run>, Vec<Run>) {
+    runs.sort_by_key(|run| (run.at, run.id));
+
+    let oldest = chrono::Utc::now().timestamp() - retention.max_age_secs();
+    let newest = runs.len().saturating_sub(1);
+    let first_kept = runs.len().saturating_sub(retention.max_runs());
+
+    let (kept, pruned) = runs
+        .into_iter()
+        .enumerate()
+        .partition::<Vec<(usize, Run)>, _>(|(i, run)| *i == newest || (*i >= first_kept && run.at >= oldest));
+
+    (kept.into_iter().map(|(_, run)| run).collect(), pruned.into_iter().map(|(_, run)| run).collect())
+}
+
+/// Open the configured storage of a profile
+pub fn open(config: &Config, data_dir: &Path) -> Box<dyn Storage> {
+    match config.storage() {
+        StorageBackend::Json => Box::new(JsonStorage::new(data_dir, config.name())),
+        StorageBackend::Sqlite => {
+            let path = data_dir.join(format!("{}.sqlite3", config.name()));
+            let json = JsonStorage::new(data_dir, config.name());
+
+            // Starting empty would announce every show as new
+            if !path.exists() && json.has_state() {
+                seed_sqlite(&path, json);
+                println!("[{}] Moved the JSON state into {}", config.name(), path.display());
+            }
+
+            Box::new(SqliteStorage::open(&path))
+        },
+    }
+}
+
+/// Open the storage of a profile only to read it, without its lock, so it can be inspected while a daemon is running
+pub fn open_read_only(config: &Config, data_dir: &Path) -> Box<dyn Storage> {
+    let json = JsonStorage::new(data_dir, config.name());
+
+    match config.storage() {
+        // Every JSON file is replaced atomically, reading it never writes
+        StorageBackend::Json => Box::new(json),
+        StorageBackend::Sqlite => {
+            let path = data_dir.join(format!("{}.sqlite3", config.name()));
+
+            // Not moved into the database yet
+            if !path.exists() && json.has_state() {
+                return Box::new(json);
+            }
+
+            Box::new(SqliteStorage::open_read_only(&path))
+        },
+    }
+}
+
+/// Copy all state into a new database, which only appears once it is complete
+fn seed_sqlite(path: &Path, mut json: JsonStorage) {
+    let tmp_path = path.with_extension("sqlite3.tmp");
+
+    // Left behind by an earlier attempt that didn't finish
+    for suffix in ["", "-wal", "-shm"] {
+        let mut stale = tmp_path.clone().into_os_string();
+        stale.push(suffix);
+        let stale = PathBuf::from(stale);
+
+        match std::fs::remove_file(&stale) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => panic!("Failed to remove {}: {err}", stale.display()),
+            _ => {},
+        }
+    }
+
+    let mut sqlite = SqliteStorage::open(&tmp_path);
+
+    sqlite.save_shows(&json.load_shows());
+    sqlite.save_messages(&json.load_messages());
+    sqlite.save_ignored(&json.load_ignored());
+    sqlite.log_notifications(&json.notifications());
+
+    for run in json.runs() {
+        match json.snapshot(run.id) {
+            Ok(Some(snapshot)) => {
+                sqlite.record_run(run.at, &snapshot, &run.changes);
+            },
+            Ok(None) => {},
+            Err(err) => eprintln!("Leaving out run #{} of the history: {err}", run.id),
+        }
+    }
+
+    // Closing checkpoints the write-ahead log into the database file
+    drop(sqlite);
+
+    std::fs::rename(&tmp_path, path).expect("Failed to move seeded state database into place");
+}
+
+/// Versioned JSON state files, with history and notification log as JSON lines
+#[derive(Debug)]
+pub struct JsonStorage {
+    shows: StateFile,
+    messages: StateFile,
+    ignored: StateFile,
+    /// One line per run, the shows they store are in `history_dir`
+    runs_path: PathBuf,
+    history_dir: PathBuf,
+    notifications_path: PathBuf,
+}
+
+impl JsonStorage {
+    pub fn new(data_dir: &Path, profile: &str) -> JsonStorage {
+        JsonStorage {
+            shows: StateFile::new(data_dir.join(format!("{profile}.json")), StateKind::Shows),
+            messages: StateFile::new(data_dir.join(format!("{profile}.messages.json")), StateKind::Messages),
+            ignored: StateFile::new(data_dir.join(format!("{profile}.ignored.json")), StateKind::Ignored),
+            runs_path: data_dir.join(format!("{profile}.runs.jsonl")),
+            history_dir: data_dir.join(format!("{profile}.history")),
+            notifications_path: data_dir.join(format!("{profile}.notifications.jsonl")),
+        }
+    }
+
+    fn append<T: Serialize>(path: &Path, records: &[T]) -> std::io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+
+        for record in records {
+            serde_json::to_writer(&mut lines, record).expect("Failed to serialize record");
+            lines.push(b'\n');
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&lines)?;
+        file.sync_data()
+    }
+
+    /// Whether any shows were saved, or at least their backup
+    fn has_state(&self) -> bool {
+        self.shows.exists()
+    }
+
+    fn snapshot_file(&self, run: u64) -> StateFile {
+        StateFile::new(self.history_dir.join(format!("{run}.json")), StateKind::Snapshot)
+    }
+
+    fn stored(&self, run: u64) -> Result<Option<StoredSnapshot>, String> {
+        self.snapshot_file(run).try_load()
+    }
+
+    /// Runs that have their shows stored in `history_dir`, pruned ones included
+    fn stored_runs(&self) -> Vec<u64> {
+        let Ok(entries) = std::fs::read_dir(&self.history_dir) else {
+            return Vec::new();
+        };
+
+        entries.flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+            .collect()
+    }
+
+    /// Remove the stored shows of runs that are gone and no longer needed by any other run
+    fn collect_garbage(&mut self) {
+        let runs = self.runs();
+
+        let needed = match needed_snapshots(&runs, |run| self.stored(run)) {
+            Ok(needed) => needed,
+            Err(err) => {
+                // Keep everything rather than losing what the unreadable one needs
+                eprintln!("Not removing pruned history: {err}");
+                return;
+            },
+        };
+
+        for run in self.stored_runs().into_iter().filter(|run| !needed.contains(run)) {
+            self.snapshot_file(run).remove().expect("Failed to remove snapshot");
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load_shows(&mut self) -> Shows {
+        self.shows.load().unwrap_or_default()
+    }
+
+    fn save_shows(&mut self, shows: &Shows) {
+        self.shows.save(shows).expect("Failed to write shows cache");
+    }
+
+    fn load_messages(&mut self) -> Messages {
+        self.messages.load().unwrap_or_default()
+    }
+
+    fn save_messages(&mut self, messages: &Messages) {
+        self.messages.save(messages).expect("Failed to write messages cache");
+    }
+
+    fn load_ignored(&mut self) -> BTreeSet<String> {
+        self.ignored.load().unwrap_or_default()
+    }
+
+    fn save_ignored(&mut self, ignored: &BTreeSet<String>) {
+        self.ignored.save(ignored).expect("Failed to write ignored shows");
+    }
+
+    fn record_run(&mut self, at: i64, shows: &Shows, changes: &[ChangeRecord]) -> u64 {
+        let runs = self.runs();
+
+        // A pruned run can still store shows of later ones, its id is never reused
+        let id = runs.iter().map(|run| run.id).chain(self.stored_runs()).max().map_or(1, |id| id + 1);
+
+        let latest = latest_snapshot(&runs, |run| self.stored(run));
+        let snapshot = StoredSnapshot::new(id, shows, latest.as_ref());
+
+        std::fs::create_dir_all(&self.history_dir).expect("Failed to create history directory");
+        self.snapshot_file(id).save(&snapshot).expect("Failed to write snapshot");
+
+        let run = Run { id, at, show_count: shows.len(), changes: changes.to_vec() };
+        JsonStorage::append(&self.runs_path, &[run]).expect("Failed to write run history");
+
+        id
+    }
+
+    fn runs(&mut self) -> Vec<Run> {
+        let Ok(runs) = std::fs::read_to_string(&self.runs_path) else {
+            return Vec::new();
+        };
+
+        // A line without its newline is still being appended
+        let mut runs = runs.split_inclusive('\n')
+            .filter(|line| line.ends_with('\n') && line.trim() != "")
+            .map(|line| serde_json::from_str(line).expect("Failed to parse run history"))
+            .collect::<Vec<Run>>();
+
+        runs.sort_by_key(|run| (run.at, run.id));
+        runs
+    }
+
+    fn snapshot(&mut self, run: u64) -> Result<Option<Shows>, String> {
+        // Pruned runs keep their file while later runs still need shows from it
+        if !self.runs().iter().any(|kept| kept.id == run) {
+            return Ok(None);
+        }
+
+        let Some(snapshot) = self.stored(run)? else {
+            return Ok(None);
+        };
+
+        snapshot.assemble(run, |run| self.stored(run)).map(Some)
+    }
+
+    fn delete_runs(&mut self, runs: &[Run]) {
+        let mut lines = Vec::new();
+
+        for run in self.runs().iter().filter(|run| !runs.iter().any(|deleted| deleted.id == run.id)) {
+            serde_json::to_writer(&mut lines, run).expect("Failed to serialize run");
+            lines.push(b'\n');
+        }
+
+        state::write_atomic(&self.runs_path, &lines).expect("Failed to write run history");
+
+        self.collect_garbage();
+    }
+
+    fn log_notifications(&mut self, records: &[NotificationRecord]) {
+        JsonStorage::append(&self.notifications_path, records).expect("Failed to write notification log");
+    }
+
+    fn notifications(&mut self) -> Vec<NotificationRecord> {
+        let Ok(records) = std::fs::read_to_string(&self.notifications_path) else {
+            return Vec::new();
+        };
+
+        // A line without its newline is still being appended
+        records.split_inclusive('\n')
+            .filter(|line| line.ends_with('\n') && line.trim() != "")
+            .map(|line| serde_json::from_str(line).expect("Failed to parse notification log"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::state::tests::test_dir;
+
+    pub fn retention(max_age_days: u64, max_runs: usize) -> HistoryConfig {
+        serde_json::from_value(serde_json::json!({ "max_age_days": max_age_days, "max_runs": max_runs })).unwrap()
+    }
+
+    /// Runs with the given ages in days, oldest first
+    fn runs(ages: &[i64]) -> Vec<Run> {
+        let now = chrono::Utc::now().timestamp();
+
+        ages.iter()
+            .enumerate()
+            .map(|(i, age)| Run { id: i as u64 + 1, at: now - age * 24 * 60 * 60, show_count: 0, changes: Vec::new() })
+            .collect()
+    }
+
+    fn ids(runs: &[Run]) -> Vec<u64> {
+        runs.iter().map(|run| run.id).collect()
+    }
+
+    #[test]
+    fn old_runs_are_pruned() {
+        let (kept, pruned) = split_retention(&retention(30, 100), runs(&[60, 40, 20, 0]));
+
+        assert_eq!(ids(&kept), vec![3, 4]);
+        assert_eq!(ids(&pruned), vec![1, 2]);
+    }
+
+    #[test]
+    fn only_the_newest_runs_are_kept() {
+        let (kept, pruned) = split_retention(&retention(30, 2), runs(&[3, 2, 1, 0]));
+
+        assert_eq!(ids(&kept), vec![3, 4]);
+        assert_eq!(ids(&pruned), vec![1, 2]);
+    }
+
+    #[test]
+    fn newest_run_is_always_kept() {
+        let (kept, pruned) = split_retention(&retention(30, 0), runs(&[90, 60]));
+
+        assert_eq!(ids(&kept), vec![2]);
+        assert_eq!(ids(&pruned), vec![1]);
+    }
+
+    #[test]
+    fn nothing_to_prune() {
+        let (kept, pruned) = split_retention(&retention(30, 10), Vec::new());
+
+        assert!(kept.is_empty() && pruned.is_empty());
+    }
+
+    #[test]
+    fn unchanged_shows_are_stored_once() {
+        let dir = test_dir("history");
+        let mut storage = JsonStorage::new(&dir, "t");
+
+        let show = Show::sample();
+        let mut changed = show.clone();
+        changed.set_thread_id("discord", String::from("123"));
+
+        let first = Shows::from([(String::from("A"), show.clone()), (String::from("B"), show.clone())]);
+        let second = Shows::from([(String::from("A"), show), (String::from("B"), changed.clone())]);
+        let third = Shows::from([(String::from("B"), changed)]);
+
+        let run = storage.record_run(1, &first, &[]);
+        storage.record_run(2, &second, &[]);
+
+        assert_eq!(storage.stored(2).unwrap().unwrap().shows.keys().collect::<Vec<&String>>(), vec!["B"]);
+
+        let runs = storage.runs();
+        storage.delete_runs(&runs[..1]);
+
+        // The second run still needs the first one's A
+        assert_eq!(storage.snapshot(run).unwrap(), None);
+        assert_eq!(storage.snapshot(2).unwrap(), Some(second));
+
+        storage.record_run(3, &third, &[]);
+        let runs = storage.runs();
+        storage.delete_runs(&runs[..1]);
+
+        let mut stored = storage.stored_runs();
+        stored.sort();
+
+        assert_eq!(stored, vec![2, 3]);
+        assert_eq!(storage.snapshot(3).unwrap(), Some(third));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+
+use crate::{notify::NotifyError, show::ShowComparison, watcher::{ChangeKind, Comparison}};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TemplatesConfig {
+    #[serde(default)]
+    new_show: MessageTemplate,
+    #[serde(default)]
+    new_seasons: MessageTemplate,
+    #[serde(default)]
+    new_episodes: MessageTemplate,
+    /// Used for every change kind when rolling messages are enabled, the message shows the whole show
+    #[serde(default)]
+    rolling: MessageTemplate,
+}
+
+/// Templates of a single change kind, unset parts fall back to the defaults
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MessageTemplate {
+    content: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    /// Fields that render to an empty value are left out
+    fields: Option<Vec<FieldTemplate>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FieldTemplate {
+    name: String,
+    value: String,
+    #[serde(default = "default_inline")]
+    inline: bool,
+}
+
+fn default_inline() -> bool {
+    true
+}
+
+impl FieldTemplate {
+    fn new(name: &str, value: &str) -> FieldTemplate {
+        FieldTemplate { name: name.to_owned(), value: value.to_owned(), inline: true }
+    }
+}
+
+impl MessageTemplate {
+    fn defaults(kind: ChangeKind) -> MessageTemplate {
+        let download = FieldTemplate::new("Download", "{{ link }}");
+
+        match kind {
+            ChangeKind::NewShow => MessageTemplate {
+                content: Some(String::from("New show!")),
+                title: Some(String::from("{{ show }}")),
+                description: None,
+                fields: Some(vec![
+                    download,
+                    FieldTemplate::new("Episodes", "{{ episode_count }}"),
+                ]),
+            },
+            ChangeKind::NewSeasons => MessageTemplate {
+                content: Some(String::from(
+                    "{% if seasons|length > 1 %}New seasons!{% else %}New season!{% endif %}"
+                )),
+                title: Some(String::from(
+                    "{{ show }} ({% if seasons|length > 1 %}Seasons {{ first_season }}-{{ last_season }}{% else %}Season {{ first_season }}{% endif %})"
+                )),
+                description: None,
+                fields: Some(vec![
+                    download,
+                    FieldTemplate::new("New Episodes", "{{ new_episodes }}"),
+                ]),
+            },
+            ChangeKind::NewEpisodes => MessageTemplate {
+                content: Some(String::from(
+                    "{% if new_episodes > 1 %}New episodes!{% else %}New episode!{% endif %}"
+                )),
+                title: Some(String::from(
+                    "{{ show }} (+{{ new_episodes }} Episode{% if new_episodes > 1 %}s{% endif %})"
+                )),
+                description: None,
+                fields: Some(vec![
+                    download,
+                    FieldTemplate::new("New Episodes", "{% if new_episodes > 1 %}{{ new_episodes }}{% endif %}"),
+                ]),
+            },
+        }
+    }
+
+    /// Cumulative status of the show, a rolling message is edited again and again
+    fn rolling_defaults() -> MessageTemplate {
+        MessageTemplate {
+            content: Some(String::from(
+                "{% if kind == 'new_show' %}New show!{% elif kind == 'new_seasons' %}New season!{% else %}New episodes!{% endif %}"
+            )),
+            title: Some(String::from("{{ show }}")),
+            description: Some(String::from(
+                "{% for s in season_episodes %}Season {{ s.season }}: {{ s.episodes }} episode{% if s.episodes != 1 %}s{% endif %}\n{% endfor %}"
+            )),
+            fields: Some(vec![
+                FieldTemplate::new("Download", "{{ link }}"),
+                FieldTemplate::new("Episodes", "{{ episode_count }}"),
+            ]),
+        }
+    }
+
+    /// Fill every unset part from `defaults`
+    fn or(self, defaults: MessageTemplate) -> MessageTemplate {
+        MessageTemplate {
+            content: self.content.or(defaults.content),
+            title: self.title.or(defaults.title),
+            description: self.description.or(defaults.description),
+            fields: self.fields.or(defaults.fields),
+        }
+    }
+}
+
+/// Variables available to every template
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    profile: &'a str,
+    kind: ChangeKind,
+    show: &'a str,
+    folder_name: &'a str,
+    /// Bracketed release tags from the folder name, e.g. `nAV1-1080p-HDR`
+    tags: Vec<String>,
+    /// New season numbers, or every season of a new show
+    seasons: Vec<usize>,
+    first_season: Option<usize>,
+    last_season: Option<usize>,
+    new_episodes: usize,
+    /// Every season of the show with its episode count
+    season_episodes: Vec<SeasonEpisodes>,
+    season_count: usize,
+    episode_count: usize,
+    /// Empty if no `message_link` is configured
+    link: &'a str,
+}
+
+#[derive(Serialize)]
+struct SeasonEpisodes {
+    season: usize,
+    episodes: usize,
+}
+
+/// A change rendered through the templates, ready to be sent by any notifier
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub comparison: Comparison,
+    pub content: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub fields: Vec<NotificationField>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NotificationField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Templates {
+    profile: String,
+    message_link: Option<String>,
+    new_show: MessageTemplate,
+    new_seasons: MessageTemplate,
+    new_episodes: MessageTemplate,
+    rolling: MessageTemplate,
+}
+
+impl Templates {
+    pub fn new(config: Option<&TemplatesConfig>, profile: &str, message_link: Option<&str>) -> Templates {
+        let config = config.cloned().unwrap_or_default();
+
+        let templates = Templates {
+            profile: profile.to_owned(),
+            message_link: message_link.map(|link| link.to_owned()),
+            new_show: config.new_show.or(MessageTemplate::defaults(ChangeKind::NewShow)),
+            new_seasons: config.new_seasons.or(MessageTemplate::defaults(ChangeKind::NewSeasons)),
+            new_episodes: config.new_episodes.or(MessageTemplate::defaults(ChangeKind::NewEpisodes)),
+            rolling: config.rolling.or(MessageTemplate::rolling_defaults()),
+        };
+
+        templates.validate();
+        templates
+    }
+
+    fn template(&self, kind: ChangeKind) -> &MessageTemplate {
+        match kind {
+            ChangeKind::NewShow => &self.new_show,
+            ChangeKind::NewSeasons => &self.new_seasons,
+            ChangeKind::NewEpisodes => &self.new_episodes,
+        }
+    }
+
+    /// Render every template with the sample changes, so mistakes show up before anything is sent.
+    /// Parsing alone would miss unknown filters and functions.
+    fn validate(&self) {
+        for comp in Comparison::samples() {
+            for result in [self.render(&comp), self.render_rolling(&comp)] {
+                if let Err(err) = result {
+                    panic!("Invalid template: {err}");
+                }
+            }
+        }
+    }
+
+    pub fn render(&self, comp: &Comparison) -> Result<Notification, NotifyError> {
+        self.render_with(self.template(comp.kind()), comp)
+    }
+
+    /// Render the status of the whole show, for a rolling message
+    pub fn render_rolling(&self, comp: &Comparison) -> Result<Notification, NotifyError> {
+        self.render_with(&self.rolling, comp)
+    }
+
+    fn render_with(&self, template: &MessageTemplate, comp: &Comparison) -> Result<Notification, NotifyError> {
+        let env = Environment::new();
+        let show = comp.show();
+
+        let (seasons, new_episodes) = match comp {
+            Comparison::NewlyAdded(show) => (
+                show.seasons().iter().map(|s| s.season_number()).collect::<Vec<usize>>(),
+                show.episode_count(),
+            ),
+            Comparison::Changed(ShowComparison::NewSeasons(_, seasons)) => (
+                seasons.iter().map(|s| s.season_number()).collect(),
+                seasons.iter().fold(0usize, |c, s| c + s.episodes()),
+            ),
+            Comparison::Changed(ShowComparison::NewEpisodes(_, episodes)) => (Vec::new(), *episodes),
+        };
+
+        let ctx = TemplateContext {
+            profile: &self.profile,
+            kind: comp.kind(),
+            show: show.title(),
+            folder_name: show.folder_name(),
+            tags: show.tags(),
+            first_season: seasons.iter().min().copied(),
+            last_season: seasons.iter().max().copied(),
+            seasons,
+            new_episodes,
+            season_episodes: show.seasons()
+                .iter()
+                .map(|s| SeasonEpisodes { season: s.season_number(), episodes: s.episodes() })
+                .collect(),
+            season_count: show.season_count(),
+            episode_count: show.episode_count(),
+            link: self.message_link.as_deref().unwrap_or_default(),
+        };
+
+        let render = |source: &str| -> Result<String, NotifyError> {
+            env.render_str(source, &ctx)
+                .map(|rendered| rendered.trim().to_owned())
+                .map_err(|err| NotifyError::Template(format!("{source:?}: {err}")))
+        };
+
+        let mut fields = Vec::new();
+
+        for field in template.fields.iter().flatten() {
+            let field = NotificationField {
+                name: render(&field.name)?,
+                value: render(&field.value)?,
+                inline: field.inline,
+            };
+
+            if !field.value.is_empty() {
+                fields.push(field);
+            }
+        }
+
+        Ok(Notification {
+            comparison: comp.clone(),
+            content: template.content.as_deref().map(render).transpose()?.unwrap_or_default(),
+            title: template.title.as_deref().map(render).transpose()?.unwrap_or_default(),
+            description: template.description
+                .as_deref()
+                .map(render)
+                .transpose()?
+                .filter(|description| !description.is_empty()),
+            fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::show::Show;
+
+    #[test]
+    fn rolling_message_shows_every_season() {
+        let templates = Templates::new(None, "test", None);
+        let comp = Comparison::Changed(ShowComparison::NewEpisodes(Show::sample(), 1));
+
+        let notification = templates.render_rolling(&comp).unwrap();
+
+        assert_eq!(notification.content, "New episodes!");
+        assert_eq!(notification.title, "Sample Show");
+        assert_eq!(notification.description.as_deref(), Some("Season 1: 12 episodes\nSeason 2: 10 episodes"));
+    }
+
+    #[test]
+    fn regular_message_shows_the_change() {
+        let templates = Templates::new(None, "test", None);
+        let comp = Comparison::Changed(ShowComparison::NewEpisodes(Show::sample(), 1));
+
+        assert_eq!(templates.render(&comp).unwrap().title, "Sample Show (+1 Episode)");
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown filter")]
+    fn unknown_filter_is_rejected_up_front() {
+        let config: TemplatesConfig = serde_json::from_str(r#"{"new_show": {"title": "{{ show|upperr }}"}}"#).unwrap();
+
+        Templates::new(Some(&config), "test", None);
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::{BTreeMap, BTreeSet}, path::Path};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::{Config, HistoryConfig},
+    state::{self, ProfileLock, StateKind, SCHEMA_VERSION},
+    storage::{self, ChangeRecord, Messages, NotificationRecord, Shows, Storage},
+};
+
+/// What to do with the state that already exists when importing
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum ImportMode {
+    /// Keep existing state and add what is missing from the export
+    #[default]
+    Merge,
+    /// Throw away existing state and use the export instead
+    Replace,
+}
+
+/// All state of several profiles, in a single JSON document
+#[derive(Deserialize, Serialize)]
+struct Export {
+    schema_version: u32,
+    tool_version: String,
+    exported_at: DateTime<Utc>,
+    profiles: BTreeMap<String, ProfileExport>,
+}
+
+/// Everything is kept as JSON, so exports of earlier schema versions can be migrated
+#[derive(Deserialize, Serialize)]
+struct ProfileExport {
+    shows: Value,
+    messages: Value,
+    #[serde(default)]
+    ignored: BTreeSet<String>,
+    #[serde(default)]
+    runs: Vec<RunExport>,
+    #[serde(default)]
+    notifications: Vec<NotificationRecord>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RunExport {
+    /// Only meaningful in the profile it was exported from
+    id: u64,
+    /// Unix timestamp in seconds
+    at: i64,
+    changes: Vec<ChangeRecord>,
+    /// The shows as they were after the run
+    shows: Value,
+}
+
+/// Write the state of every profile to a file, or to stdout without one
+pub fn export(configs: &[Config], path: Option<&Path>) {
+    let data_dir = state::data_dir();
+    let mut profiles = BTreeMap::new();
+
+    // Exporting only reads, so it works while a daemon keeps the profiles locked
+    for config in configs {
+        let mut storage = storage::open_read_only(config, &data_dir);
+
+        profiles.insert(config.name().to_owned(), export_profile(storage.as_mut()));
+    }
+
+    let export = Export {
+        schema_version: SCHEMA_VERSION,
+        tool_version: String::from(env!("CARGO_PKG_VERSION")),
+        exported_at: Utc::now(),
+        profiles,
+    };
+
+    let json = serde_json::to_string_pretty(&export).expect("Failed to serialize export");
+
+    match path {
+        Some(path) => {
+            std::fs::write(path, json).expect("Failed to write export");
+            eprintln!("Exported {} profiles to {}", export.profiles.len(), path.display());
+        },
+        None => println!("{json}"),
+    }
+}
+
+fn export_profile(storage: &mut dyn Storage) -> ProfileExport {
+    let runs = storage.runs()
+        .into_iter()
+        .filter_map(|run| {
+            let shows = storage.snapshot(run.id)
+                .inspect_err(|err| eprintln!("Leaving out run #{} of the export: {err}", run.id))
+                .ok()??;
+
+            Some(RunExport {
+                id: run.id,
+                at: run.at,
+                changes: run.changes,
+                shows: serde_json::to_value(shows).expect("Failed to serialize snapshot"),
+            })
+        })
+        .collect();
+
+    ProfileExport {
+        shows: serde_json::to_value(storage.load_shows()).expect("Failed to serialize shows"),
+        messages: serde_json::to_value(storage.load_messages()).expect("Failed to serialize messages"),
+        ignored: storage.load_ignored(),
+        runs,
+        notifications: storage.notifications(),
+    }
+}
+
+fn migrate<T: DeserializeOwned>(kind: StateKind, version: u32, value: Value) -> T {
+    let value = state::migrate_data(kind, version, value).unwrap_or_else(|err| panic!("Failed to migrate export: {err}"));
+    serde_json::from_value(value).unwrap_or_else(|err| panic!("Export is invalid: {err}"))
+}
+
+/// Load exported state into the profiles of the config
+pub fn import(configs: &[Config], path: &Path, mode: ImportMode) {
+    let json = std::fs::read_to_string(path).expect("Failed to read export");
+    let mut export: Export = serde_json::from_str(&json).expect("Failed to parse export");
+    let version = export.schema_version;
+
+    if version > SCHEMA_VERSION {
+        panic!("Export has schema version {version} from nav1-watcher {}, this one supports up to {SCHEMA_VERSION}!", export.tool_version);
+    }
+
+    for name in export.profiles.keys() {
+        if !configs.iter().any(|config| config.name() == name) {
+            eprintln!("Skipping profile {name}, it is not selected or not in the config");
+        }
+    }
+
+    let data_dir = state::data_dir();
+
+    for config in configs {
+        let Some(profile) = export.profiles.remove(config.name()) else {
+            eprintln!("Profile {} is not in the export, leaving it as it is", config.name());
+            continue;
+        };
+
+        let _lock = ProfileLock::acquire(&data_dir, config.name());
+        let mut storage = storage::open(config, &data_dir);
+
+        let (shows, runs) = import_profile(storage.as_mut(), profile, version, mode, config.history());
+
+        println!("Imported profile {}: {shows} shows, {runs} runs", config.name());
+    }
+}
+
+/// Import one profile into its storage, returns how many shows it has and how many runs were added
+fn import_profile(storage: &mut dyn Storage, profile: ProfileExport, version: u32, mode: ImportMode, history: &HistoryConfig) -> (usize, usize) {
+    let shows: Shows = migrate(StateKind::Shows, version, profile.shows);
+    let messages: Messages = migrate(StateKind::Messages, version, profile.messages);
+
+    let mut runs = profile.runs
+        .into_iter()
+        .map(|run| {
+            let snapshot: Shows = migrate(StateKind::Shows, version, run.shows);
+
+            (run.at, run.changes, snapshot)
+        })
+        .collect::<Vec<(i64, Vec<ChangeRecord>, Shows)>>();
+
+    let (shows, messages, ignored, mut runs) = match mode {
+        ImportMode::Replace => {
+            let existing = storage.runs();
+            storage.delete_runs(&existing);
+
+            (shows, messages, profile.ignored, runs)
+        },
+        ImportMode::Merge => {
+            let mut merged_shows = storage.load_shows();
+
+            for (title, show) in shows {
+                match merged_shows.get_mut(&title) {
+                    // Keep the local show, but learn about threads it doesn't know yet
+                    Some(existing) => {
+                        for (target, thread_id) in show.threads() {
+                            if existing.thread_id(target).is_none() {
+                                existing.set_thread_id(target, thread_id.clone());
+                            }
+                        }
+                    },
+                    None => {
+                        merged_shows.insert(title, show);
+                    },
+                }
+            }
+
+            let mut merged_messages = storage.load_messages();
+
+            for (target, messages) in messages {
+                let existing = merged_messages.entry(target).or_default();
+
+                for (title, message) in messages {
+                    existing.entry(title).or_insert(message);
+                }
+            }
+
+            let mut ignored = storage.load_ignored();
+            ignored.extend(profile.ignored);
+
+            // Runs recorded at the same time are the same run, imported before or exported from here
+            let known = storage.runs().iter().map(|run| run.at).collect::<BTreeSet<i64>>();
+            runs.retain(|(at, _, _)| !known.contains(at));
+
+            (merged_shows, merged_messages, ignored, runs)
+        },
+    };
+
+    storage.save_shows(&shows);
+    storage.save_messages(&messages);
+    storage.save_ignored(&ignored);
+
+    // The log records what was sent from where, so even replacing only adds the deliveries it doesn't have yet
+    let logged = storage.notifications()
+        .into_iter()
+        .map(|record| (record.at, record.target, record.show))
+        .collect::<BTreeSet<(i64, String, String)>>();
+
+    let notifications = profile.notifications
+        .into_iter()
+        .filter(|record| !logged.contains(&(record.at, record.target.clone(), record.show.clone())))
+        .collect::<Vec<NotificationRecord>>();
+
+    storage.log_notifications(&notifications);
+
+    // Runs are ordered by when they happened, not by id, so older imported runs fall into place
+    runs.sort_by_key(|(at, _, _)| *at);
+
+    for (at, changes, snapshot) in &runs {
+        storage.record_run(*at, snapshot, changes);
+    }
+
+    storage.prune_runs(history);
+
+    (shows.len(), runs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{show::Show, state::tests::test_dir, storage::{tests::retention, JsonStorage}, watcher::ChangeKind};
+
+    /// Storage in an empty directory of its own
+    fn storage(name: &str) -> (std::path::PathBuf, JsonStorage) {
+        let dir = test_dir(name);
+        let storage = JsonStorage::new(&dir, "test");
+        (dir, storage)
+    }
+
+    fn shows(titles: &[&str]) -> Shows {
+        titles
+            .iter()
+            .map(|title| {
+                let show = Show::from_parts(title.to_string(), title.to_string(), Vec::new(), None, BTreeMap::new());
+                (title.to_string(), show)
+            })
+            .collect()
+    }
+
+    fn exported(shows: Shows, ignored: &[&str], runs: &[i64]) -> ProfileExport {
+        ProfileExport {
+            shows: serde_json::to_value(&shows).unwrap(),
+            messages: serde_json::json!({}),
+            ignored: ignored.iter().map(|title| title.to_string()).collect(),
+            runs: runs
+                .iter()
+                .enumerate()
+                .map(|(i, at)| RunExport {
+                    id: i as u64 + 1,
+                    at: *at,
+                    changes: Vec::new(),
+                    shows: serde_json::to_value(&shows).unwrap(),
+                })
+                .collect(),
+            notifications: Vec::new(),
+        }
+    }
+
+    fn sorted_titles(shows: &Shows) -> Vec<&str> {
+        let mut titles = shows.keys().map(|title| title.as_str()).collect::<Vec<&str>>();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn merge_keeps_local_state_and_orders_runs_by_time() {
+        let (dir, mut storage) = storage("merge");
+
+        let mut local = shows(&["Local", "Both"]);
+        storage.save_shows(&local);
+        storage.save_ignored(&BTreeSet::from([String::from("Local")]));
+        storage.record_run(1_000, &local, &[]);
+        storage.record_run(3_000, &local, &[]);
+
+        let mut remote = shows(&["Remote", "Both"]);
+        remote.get_mut("Both").unwrap().set_thread_id("discord", String::from("42"));
+        local.get_mut("Both").unwrap().set_thread_id("discord", String::from("42"));
+
+        let (show_count, run_count) = import_profile(&mut storage, exported(remote, &["Remote"], &[2_000, 3_000]), SCHEMA_VERSION, ImportMode::Merge, &retention(36500, 100));
+
+        assert_eq!((show_count, run_count), (3, 1));
+
+        let merged = storage.load_shows();
+        assert_eq!(sorted_titles(&merged), vec!["Both", "Local", "Remote"]);
+        assert_eq!(merged["Both"], local["Both"]);
+        assert_eq!(storage.load_ignored(), BTreeSet::from([String::from("Local"), String::from("Remote")]));
+
+        let runs = storage.runs();
+        assert_eq!(runs.iter().map(|run| run.at).collect::<Vec<i64>>(), vec![1_000, 2_000, 3_000]);
+        // The older imported run got the highest id, the newest run stays last
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<u64>>(), vec![1, 3, 2]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn merging_twice_adds_nothing() {
+        let (dir, mut storage) = storage("merge-twice");
+
+        for _ in 0..2 {
+            let mut profile = exported(shows(&["Remote"]), &[], &[1_000, 2_000]);
+            profile.notifications = vec![NotificationRecord {
+                at: 2_000,
+                target: String::from("discord"),
+                show: String::from("Remote"),
+                kind: ChangeKind::NewShow,
+                error: None,
+            }];
+
+            import_profile(&mut storage, profile, SCHEMA_VERSION, ImportMode::Merge, &retention(36500, 100));
+        }
+
+        assert_eq!(storage.runs().len(), 2);
+        assert_eq!(storage.notifications().len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replace_drops_local_state() {
+        let (dir, mut storage) = storage("replace");
+
+        let local = shows(&["Local"]);
+        storage.save_shows(&local);
+        storage.save_ignored(&BTreeSet::from([String::from("Local")]));
+        storage.record_run(5_000, &local, &[]);
+
+        import_profile(&mut storage, exported(shows(&["Remote"]), &[], &[1_000]), SCHEMA_VERSION, ImportMode::Replace, &retention(36500, 100));
+
+        assert_eq!(sorted_titles(&storage.load_shows()), vec!["Remote"]);
+        assert!(storage.load_ignored().is_empty());
+        assert_eq!(storage.runs().iter().map(|run| run.at).collect::<Vec<i64>>(), vec![1_000]);
+        let run = storage.runs()[0].id;
+        assert_eq!(storage.snapshot(run).unwrap().map(|shows| shows.len()), Some(1));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,67 +1,79 @@
-use std::{collections::HashMap, fs::read_dir, path::PathBuf};
+use std::{collections::{BTreeSet, HashMap, HashSet}, fs::read_dir, sync::Arc};
 
 use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
 
-use crate::{config::Config, show::{Show, ShowComparison}, webhook::{Embed, Field, Footer, Webhook}};
+use crate::{config::Config, delivery, discord::{webhook_url, Delivered, Discord, Job}, email::Mailer, feed, http, mqtt::{self, Mqtt}, notify::NotifyError, route::{self, Router}, show::{Show, ShowComparison}, state::ProfileLock, storage::{self, ChangeRecord, Messages, NotificationRecord, PostedMessage, Storage}, template::{Notification, Templates}};
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Watcher {
     config: Config,
-    data_dir: PathBuf,
     no_webhook: bool,
+    mailer: Option<Mailer>,
+    templates: Templates,
+    discord: Arc<Discord>,
+    router: Router,
     /// String is the CLEANED title!
     shows: HashMap<String, Show>,
+    /// Rolling Discord message per target and show, keyed by target name and CLEANED title
+    messages: Messages,
+    /// CLEANED titles that are never notified about
+    ignored: BTreeSet<String>,
+    storage: Box<dyn Storage>,
+    /// Connected on first use and kept open, every scan publishes stats
+    mqtt: Option<Mqtt>,
+    /// Held for as long as the watcher lives
+    _lock: ProfileLock,
 }
 
 impl Watcher {
-    pub fn new(config_path: &PathBuf, no_webhook: bool) -> Watcher {
-
-        let config_str = std::fs::read_to_string(config_path).expect("Failed to read config");
-        let config: Config = serde_json::from_str(&config_str).expect("Failed to serialize config");
-
+    pub fn new(config: Config, no_webhook: bool) -> Watcher {
         if !config.watch_folder().exists() {
-            panic!("Watch folder does not exist!");
+            panic!("Watch folder of profile {:?} does not exist!", config.name());
         }
 
         if let Some(proj_dirs) = ProjectDirs::from("xyz", "superyu", "nav1truenas") {
             let data_dir = proj_dirs.data_dir().to_path_buf();
 
-            let mut shows = HashMap::new();
+            let lock = ProfileLock::acquire(&data_dir, config.name());
 
-            if let Ok(dirs_str) = std::fs::read_to_string(data_dir.join(format!("{}.json", config.name()))) {
-                shows = serde_json::from_str(&dirs_str).expect("Failed to serialize shows cache");
-            }
+            let mut storage = storage::open(&config, &data_dir);
+            let shows = storage.load_shows();
+            let messages = storage.load_messages();
+            let ignored = storage.load_ignored();
+
+            let mailer = config.smtp().map(Mailer::new);
+            let templates = Templates::new(config.templates(), config.name(), config.message_link());
+            let client = http::client(config.http());
+            let discord = Arc::new(Discord::new(&config, client));
+            let router = Router::new(&config);
 
             return Watcher {
                 config,
-                data_dir,
                 shows,
-                no_webhook
+                no_webhook,
+                mailer,
+                templates,
+                discord,
+                router,
+                messages,
+                ignored,
+                storage,
+                mqtt: None,
+                _lock: lock,
             }
         }
 
         panic!("Could not get data directory!");
     }
 
-    fn persist_shows(&self) {
-        let persist_directory = self.data_dir.join(format!("{}.json", self.config.name()));
-
-        if !self.data_dir.exists() {
-            std::fs::create_dir_all(&self.data_dir).expect("Failed to create data directory");
-        }
-
-        let shows_str = serde_json::to_string(&self.shows).expect("Failed to serialize directories");
-        std::fs::write(persist_directory, shows_str).expect("Failed to write shows cache");
-    }
-
     pub fn read_new(&mut self) -> HashMap<String, Show> {
         let mut new = HashMap::new();
 
         for dir_res in read_dir(self.config.watch_folder()).expect("Fild to read watch directory") {
             if let Ok(dir) = dir_res {
-                if dir.file_type().expect("Failed to get file type").is_dir() {
-                    let show = Show::from_folder(&dir.path());
-                    new.insert(show.title().to_owned(), show);
+                if dir.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    self.scan_folder(&mut new, &dir.file_name().to_string_lossy());
                 }
             }
         }
@@ -69,8 +81,54 @@ impl Watcher {
         new
     }
 
+    /// Scan one show folder into `new`. A folder that vanished is left out,
+    /// one that can't be read keeps its previous state so it isn't announced again later.
+    fn scan_folder(&self, new: &mut HashMap<String, Show>, folder: &str) {
+        let path = self.config.watch_folder().join(folder);
+
+        match Show::from_folder(&path) {
+            Ok(show) => {
+                new.insert(show.title().to_owned(), show);
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+            Err(err) => {
+                eprintln!("[{}] Failed to scan {}, keeping its previous state: {err}", self.config.name(), path.display());
+
+                for (title, show) in self.shows.iter().filter(|(_, show)| show.folder_name() == folder) {
+                    new.insert(title.clone(), show.clone());
+                }
+            },
+        }
+    }
+
     pub fn update_shows(&mut self) -> Vec<Comparison> {
         let new = self.read_new();
+        self.apply_shows(new)
+    }
+
+    /// Re-scan only the given show folders, keeping every other show as it is
+    pub fn update_folders(&mut self, folders: &HashSet<String>) -> Vec<Comparison> {
+        let mut new = self.shows
+            .iter()
+            .filter(|(_, show)| !folders.contains(show.folder_name()))
+            .map(|(k, show)| (k.clone(), show.clone()))
+            .collect::<HashMap<String, Show>>();
+
+        for folder in folders {
+            if self.config.watch_folder().join(folder).is_dir() {
+                self.scan_folder(&mut new, folder);
+            }
+        }
+
+        self.apply_shows(new)
+    }
+
+    fn apply_shows(&mut self, mut new: HashMap<String, Show>) -> Vec<Comparison> {
+        for (k, show) in new.iter_mut() {
+            if let Some(other) = self.shows.get(k) {
+                show.keep_state(other);
+            }
+        }
 
         let mut comparisons = new
             .clone()
@@ -93,196 +151,408 @@ impl Watcher {
             .map(|(_, v)| Comparison::NewlyAdded(v))
             .collect::<Vec<Comparison>>();
 
+        let changed = new != self.shows;
+
         self.shows = new;
-        self.persist_shows();
+        self.storage.save_shows(&self.shows);
 
         comparisons.append(&mut new_shows);
+
+        // Scans that found nothing new would only store the same snapshot again
+        if changed {
+            let now = chrono::Utc::now().timestamp();
+            let changes = comparisons
+                .iter()
+                .map(|comp| ChangeRecord::new(comp, now))
+                .collect::<Vec<ChangeRecord>>();
+
+            self.storage.record_run(now, &self.shows, &changes);
+            self.storage.prune_runs(self.config.history());
+        }
+
+        self.publish_stats();
+
         comparisons
     }
 
-    fn fire_webhook<'a>(&self, comp: Comparison) {
-        let reqwest = reqwest::blocking::Client::new();
+    /// Update the retained library stats after every scan, whether or not anything is notified
+    fn publish_stats(&mut self) {
+        let Some(mqtt_config) = self.config.mqtt() else {
+            return;
+        };
 
-        let content = {
-            let msg = match &comp {
-                Comparison::NewlyAdded(_) => {
-                    format!("New show!")
-                },
-                Comparison::Changed(showcomp) => {
-                    match showcomp {
-                        ShowComparison::NewSeasons(_, seasons) => {
-                            if seasons.len() > 1 {
-                                format!("New seasons!")
-                            } else {
-                                format!("New season!")
-                            }
-                        },
-                        ShowComparison::NewEpisodes(_, episodes) => {
-                            if *episodes > 1 {
-                                format!("New episodes!")
-                            } else {
-                                format!("New episode!")
-                            }
-                        },
-                        
-                    }
-                },
-            };
+        let profile = self.config.name();
+        let mqtt = self.mqtt.get_or_insert_with(|| Mqtt::connect(mqtt_config, profile));
 
-            if let Some(role) = self.config.role_ping_id() {
-                format!("<@&{role}> {msg}")
-            } else {
-                format!("{msg}")
-            }
+        if let Err(err) = mqtt.publish_stats(self.shows.values()) {
+            eprintln!("Failed to publish stats to {}: {err}", route::MQTT);
+        }
+    }
+
+    /// Discord job for a notification, with the thread and rolling message of earlier runs
+    fn discord_job(&self, target: &str, webhook_link: &str, notification: &Notification, now: i64) -> Result<Job, NotifyError> {
+        let title = notification.comparison.show().title();
+
+        let thread_id = self.shows
+            .get(title)
+            .and_then(|show| show.thread_id(target))
+            .map(|id| id.to_owned());
+
+        let rolling_message_id = self.config.rolling_message().and_then(|rolling| {
+            self.messages
+                .get(target)
+                .and_then(|messages| messages.get(title))
+                .filter(|message| now - message.posted_at < rolling.max_age_secs())
+                .map(|message| message.id.clone())
+        });
+
+        // A rolling message shows the whole show, not just the latest change
+        let notification = match self.config.rolling_message() {
+            Some(_) => self.templates.render_rolling(&notification.comparison)?,
+            None => notification.clone(),
         };
 
-        let fields = {
-            let mut fields = Vec::new();
+        Ok(Job {
+            target: target.to_owned(),
+            webhook_link: webhook_link.to_owned(),
+            notification,
+            thread_id,
+            rolling_message_id,
+        })
+    }
+
+    /// Remember the thread and rolling message a delivered job left behind
+    fn delivered(&mut self, job: &Job, delivered: Delivered, now: i64) {
+        let title = job.notification.comparison.show().title();
 
-            if let Some(link) = self.config.message_link() {
-                fields.push(
-                    Field::builder(
-                        String::from("Download"), 
-                        link.to_owned()
-                    ).inline(true).build()
-                )
+        if let Some(thread_id) = delivered.thread_id {
+            if let Some(show) = self.shows.get_mut(title) {
+                show.set_thread_id(&job.target, thread_id);
+                self.storage.save_show(&self.shows, title);
             }
+        }
 
-            match &comp {
-                Comparison::NewlyAdded(show) => {
-                    fields.push(
-                        Field::builder(
-                            String::from("Episodes"),
-                            format!("{}", show.episode_count())
-                        ).inline(true).build()
-                    )
-                },
-                Comparison::Changed(showcomp) => {
-                    match showcomp {
-                        ShowComparison::NewSeasons(_, seasons) => {
-                            let new_episodes = seasons
-                                .iter()
-                                .fold(0usize, |mut c, s| {
-                                    c += s.episodes();
-                                    c
-                                });
-
-                            fields.push(
-                                Field::builder(
-                                    String::from("New Episodes"),
-                                    format!("{}", new_episodes)
-                                ).inline(true).build()
-                            );
-                        },
-                        ShowComparison::NewEpisodes(_, episodes) => {
-                            if *episodes > 1 {
-                                fields.push(
-                                    Field::builder(
-                                        String::from("New Episodes"),
-                                        format!("{}", episodes)
-                                    ).inline(true).build()
-                                );
-                            }
-                        },
-                    }
+        if let Some(id) = delivered.rolling_message_id {
+            self.messages
+                .entry(job.target.clone())
+                .or_default()
+                .insert(title.to_owned(), PostedMessage { id, posted_at: now });
+            self.storage.save_messages(&self.messages);
+        }
+    }
+
+    pub fn run(&mut self) {
+        let comparisons = self.update_shows();
+        self.notify(comparisons);
+    }
+
+    /// Send the changes of shows that aren't ignored, returns how many deliveries failed
+    pub fn notify(&mut self, mut comparisons: Vec<Comparison>) -> usize {
+        comparisons.retain(|comp| !self.is_ignored(comp.show().title()));
+        self.send(comparisons)
+    }
+
+    /// Send the changes to every target they are routed to, returns how many deliveries failed
+    fn send(&mut self, comparisons: Vec<Comparison>) -> usize {
+        if self.no_webhook {
+            return 0;
+        }
+
+        let mut failed = 0;
+        let mut log = Vec::new();
+
+        let mut routed = Vec::new();
+
+        for comp in &comparisons {
+            match self.templates.render(comp) {
+                Ok(notification) => routed.push((notification, self.router.targets(comp))),
+                Err(err) => {
+                    eprintln!("Failed to notify about {}: {err}", comp.show().title());
+                    failed += 1;
                 },
             }
+        }
 
-            fields
+        let routed_to = |target: &str| -> Vec<Notification> {
+            routed
+                .iter()
+                .filter(|(_, targets)| targets.iter().any(|t| t == target))
+                .map(|(notification, _)| notification.clone())
+                .collect()
         };
 
-        let title = {
-            match &comp {
-                Comparison::NewlyAdded(show) => format!("{}", show.title()),
-                Comparison::Changed(showcomp) => {
-                    match showcomp {
-                        ShowComparison::NewSeasons(show, seasons) => {
-                            if seasons.len() == 1 {
-                                let first = seasons.first().expect("Failed to get first element");
-                                format!(
-                                    "{} (Season {})", 
-                                    show.title(), 
-                                    first.season_number()
-                                )
-                            } else {
-                                let first = seasons.first().expect("Failed to get first element");
-                                let last = seasons.last().expect("Failed to get first element");
-                                format!(
-                                    "{} (Seasons {}-{})",
-                                    show.title(),
-                                    first.season_number(),
-                                    last.season_number()
-                                )
-                            }
-                        },
-                        ShowComparison::NewEpisodes(show, episodes) => {
-                            if *episodes > 1 {
-                                format!(
-                                    "{} (+{} Episodes)",
-                                    show.title(),
-                                    episodes
-                                )
-                            } else {
-                                format!(
-                                    "{} (+{} Episode)",
-                                    show.title(),
-                                    episodes
-                                )
-                            }
-                        },
-                    }
+        let now = chrono::Utc::now().timestamp();
+
+        let jobs = self.config
+            .discord_targets()
+            .into_iter()
+            .flat_map(|(name, webhook_link)| {
+                routed_to(&name)
+                    .iter()
+                    .filter_map(|notification| {
+                        self.discord_job(&name, &webhook_link, notification, now)
+                            .inspect_err(|err| {
+                                eprintln!("Failed to notify {name} about {}: {err}", notification.title);
+                                failed += 1;
+                            })
+                            .ok()
+                    })
+                    .collect::<Vec<Job>>()
+            })
+            .collect::<Vec<Job>>();
+
+        let discord = self.discord.clone();
+        let delivery_config = self.config.delivery().clone();
+
+        delivery::runtime().block_on(delivery::deliver_all(discord, &delivery_config, jobs, |job, result| {
+            let error = result.as_ref().err().map(|err| err.to_string());
+            log.push(NotificationRecord::new(&job.target, &job.notification, error, now));
+
+            match result {
+                Ok(delivered) => self.delivered(&job, delivered, now),
+                Err(err) => {
+                    eprintln!("Failed to notify {} about {}: {err}", job.target, job.notification.title);
+                    failed += 1;
                 },
             }
+        }));
+
+        // The other notifiers send all changes at once, so they all share the outcome
+        let mut log_batch = |target: &str, notifications: &[Notification], result: &Result<(), NotifyError>| {
+            let error = result.as_ref().err().map(|err| err.to_string());
+
+            for notification in notifications {
+                log.push(NotificationRecord::new(target, notification, error.clone(), now));
+            }
+
+            if let Err(err) = result {
+                eprintln!("Failed to notify {target}: {err}");
+                failed += 1;
+            }
         };
 
-        let webhook = Webhook::builder()
-            .content(content)
-            .username(
-                String::from(
-                    "nAV1"
-                )
-            )
-            .avatar_url(
-                String::from(
-                    "https://cdn.discordapp.com/avatars/378993911609425920/ec1be00c3b542352f85c8a56d246c429?size=1024"
-                )
-            )
-            .embeds(
-                vec![
-                    Embed::builder()
-                        .title(title)
-                        .color(self.config.color())
-                        .fields(fields)
-                        .footer(
-                            Footer::builder(
-                                String::from(
-                                    "superyu"
-                                )
-                            )
-                            .icon_url(
-                                String::from(
-                                    "https://cdn.discordapp.com/avatars/378993911609425920/ec1be00c3b542352f85c8a56d246c429?size=64"
-                                )
-                            )
-                            .build()
-                        ).build()
-                ]
-            )
-            .build();
-
-        reqwest.post(self.config.webhook_link())
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .body(webhook.to_json())
-            .send().expect("Failed to send");
+        if let Some(mailer) = &self.mailer {
+            let notifications = routed_to(route::EMAIL);
+            log_batch(route::EMAIL, &notifications, &mailer.send(&notifications));
+        }
+
+        if let Some(feed) = self.config.feed() {
+            let notifications = routed_to(route::FEED);
+            log_batch(route::FEED, &notifications, &feed::append(feed, self.config.name(), &notifications, self.config.message_link()));
+        }
+
+        if let Some(mqtt_config) = self.config.mqtt() {
+            let profile = self.config.name();
+            let mqtt = self.mqtt.get_or_insert_with(|| Mqtt::connect(mqtt_config, profile));
+            let notifications = routed_to(route::MQTT);
+
+            let published = notifications
+                .iter()
+                .try_for_each(|notification| mqtt.publish_change(notification));
+
+            log_batch(route::MQTT, &notifications, &published);
+        }
+
+        self.storage.log_notifications(&log);
+
+        failed
     }
 
-    pub fn run(&mut self) {
-        let comparisons = self.update_shows();
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        if !self.no_webhook {
-            for comp in comparisons {
-                self.fire_webhook(comp);
+    /// CLEANED title of a tracked show, ignoring case
+    fn find_title(&self, title: &str) -> Option<String> {
+        self.shows
+            .keys()
+            .find(|k| k.to_lowercase() == title.to_lowercase())
+            .cloned()
+    }
+
+    fn tracked_title(&self, title: &str) -> String {
+        self.find_title(title)
+            .unwrap_or_else(|| panic!("No show titled {title:?} is tracked in profile {:?}!", self.config.name()))
+    }
+
+    fn is_ignored(&self, title: &str) -> bool {
+        self.ignored.iter().any(|ignored| ignored.to_lowercase() == title.to_lowercase())
+    }
+
+    /// Drop a show from the state, so the next scan announces it as new
+    pub fn forget(&mut self, title: &str) {
+        let title = self.tracked_title(title);
+
+        self.shows.remove(&title);
+        self.storage.save_shows(&self.shows);
+
+        for messages in self.messages.values_mut() {
+            messages.remove(&title);
+        }
+
+        self.storage.save_messages(&self.messages);
+
+        println!("[{}] Forgot {title}", self.config.name());
+    }
+
+    /// Keep tracking a show without ever notifying about it, shows that aren't tracked yet can be ignored too
+    pub fn ignore(&mut self, title: &str, undo: bool) {
+        let title = self.find_title(title).unwrap_or_else(|| title.to_owned());
+
+        if undo {
+            self.ignored.retain(|ignored| ignored.to_lowercase() != title.to_lowercase());
+            println!("[{}] No longer ignoring {title}", self.config.name());
+        } else {
+            self.ignored.insert(title.clone());
+            println!("[{}] Ignoring {title}", self.config.name());
+        }
+
+        self.storage.save_ignored(&self.ignored);
+    }
+
+    /// Accept what is on disk without notifying about it
+    pub fn mark_seen(&mut self) {
+        let changes = self.update_shows().len();
+        println!("[{}] Marked {changes} changes as seen", self.config.name());
+    }
+
+    /// Send the latest change of a show again, even if it is ignored
+    pub fn resend(&mut self, title: &str) -> usize {
+        let title = self.tracked_title(title);
+
+        let comparison = self.last_change(&title)
+            .unwrap_or_else(|err| panic!("[{}] Can't resend {title}: {err}", self.config.name()));
+
+        self.send(vec![comparison])
+    }
+
+    /// Rebuild the latest change of a show from the snapshots around it
+    fn last_change(&mut self, title: &str) -> Result<Comparison, String> {
+        let runs = self.storage.runs();
+
+        let (i, change) = runs
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, run)| Some((i, run.changes.iter().find(|change| change.show == title)?)))
+            .ok_or_else(|| String::from("no change of it is in the history"))?;
+
+        let new = self.storage
+            .snapshot(runs[i].id)?
+            .and_then(|mut shows| shows.remove(title))
+            .ok_or_else(|| format!("the snapshot of run #{} is gone", runs[i].id))?;
+
+        if change.kind == ChangeKind::NewShow {
+            return Ok(Comparison::NewlyAdded(new));
+        }
+
+        let old = match i.checked_sub(1) {
+            Some(previous) => self.storage.snapshot(runs[previous].id)?,
+            None => None,
+        };
+
+        let old = old
+            .and_then(|mut shows| shows.remove(title))
+            .ok_or_else(|| format!("the snapshot before run #{} is gone", runs[i].id))?;
+
+        new.compare(&old)
+            .map(Comparison::Changed)
+            .ok_or_else(|| format!("the snapshots around run #{} show no change", runs[i].id))
+    }
+
+}
+
+/// Send a sample of every change kind to every configured target, ignoring routes.
+/// The state of the profile is never touched, so this works next to a running daemon.
+/// Samples never create forum posts or ping anyone, with forum threads they go to `thread_id`.
+pub fn test_notify(config: &Config, dry_run: bool, thread_id: Option<&str>) {
+    let templates = Templates::new(config.templates(), config.name(), config.message_link());
+    let discord = Discord::new(config, http::client(config.http()));
+    let mailer = config.smtp().map(Mailer::new);
+
+    let samples = Comparison::samples()
+        .iter()
+        .map(|comp| templates.render(comp).expect("Sample failed to render after validation"))
+        .collect::<Vec<Notification>>();
+
+    let report = |target: &str, kind: &str, result: Result<String, NotifyError>| {
+        match result {
+            Ok(status) => println!("{target} [{kind}]: {status}"),
+            Err(err) => println!("{target} [{kind}]: FAILED {err}"),
+        }
+    };
+
+    let runtime = delivery::runtime();
+
+    let thread_id = thread_id.filter(|_| config.forum_threads());
+
+    for (name, webhook_link) in config.discord_targets() {
+        if config.forum_threads() && thread_id.is_none() && !dry_run {
+            println!("{name} [all]: skipped, pass --thread-id of an existing forum post");
+            continue;
+        }
+
+        for notification in &samples {
+            let kind = notification.comparison.kind().as_str();
+
+            let result = discord.build_sample(notification).and_then(|(webhook, artwork)| {
+                if dry_run {
+                    return Ok(webhook.to_json());
+                }
+
+                let url = webhook_url(&webhook_link, None, thread_id);
+                runtime.block_on(discord.send(reqwest::Method::POST, url, &webhook, artwork))
+                    .map(|response| response.status().to_string())
+            });
+
+            report(&name, kind, result);
+        }
+    }
+
+    if let Some(mailer) = &mailer {
+        let result = if dry_run {
+            Ok(mailer.preview(&samples)
+                .into_iter()
+                .map(|(subject, text)| format!("Subject: {subject}\n{text}"))
+                .collect::<Vec<String>>()
+                .join("\n"))
+        } else {
+            mailer.send(&samples).map(|_| String::from("sent"))
+        };
+
+        report(route::EMAIL, "all", result);
+    }
+
+    if let Some(mqtt_config) = config.mqtt() {
+        // A dry run never connects to the broker
+        if dry_run {
+            for notification in &samples {
+                let (topic, payload) = mqtt::change_message(mqtt_config, config.name(), notification);
+                report(route::MQTT, notification.comparison.kind().as_str(), Ok(format!("{topic} {}", String::from_utf8_lossy(&payload))));
             }
+        } else {
+            let mqtt = Mqtt::connect(mqtt_config, config.name());
+
+            for notification in &samples {
+                let result = mqtt.publish_change(notification).map(|_| String::from("queued"));
+                report(route::MQTT, notification.comparison.kind().as_str(), result);
+            }
+
+            let result = mqtt.disconnect().map(|_| String::from("connected and flushed"));
+            report(route::MQTT, "connection", result);
+        }
+    }
+
+    if let Some(feed) = config.feed() {
+        println!("{} [all]: skipped, samples are never written to {}", route::FEED, feed.path().display());
+    }
+}
+
+impl Drop for Watcher {
+    /// Queued MQTT messages would be lost when the process exits right after a run
+    fn drop(&mut self) {
+        if let Some(Err(err)) = self.mqtt.take().map(Mqtt::disconnect) {
+            eprintln!("[{}] Failed to flush {}: {err}", self.config.name(), route::MQTT);
         }
     }
 }
@@ -291,4 +561,54 @@ impl Watcher {
 pub enum Comparison {
     NewlyAdded(Show),
     Changed(ShowComparison),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum ChangeKind {
+    NewShow,
+    NewSeasons,
+    NewEpisodes,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::NewShow => "new_show",
+            ChangeKind::NewSeasons => "new_seasons",
+            ChangeKind::NewEpisodes => "new_episodes",
+        }
+    }
+}
+
+impl Comparison {
+    /// One made up change of every kind, used to test notifiers
+    pub fn samples() -> Vec<Comparison> {
+        let show = Show::sample();
+        let new_season = show.seasons().last().expect("Sample show has no seasons").clone();
+
+        vec![
+            Comparison::NewlyAdded(show.clone()),
+            Comparison::Changed(ShowComparison::NewSeasons(show.clone(), vec![new_season])),
+            Comparison::Changed(ShowComparison::NewEpisodes(show, 3)),
+        ]
+    }
+
+    pub fn kind(&self) -> ChangeKind {
+        match self {
+            Comparison::NewlyAdded(_) => ChangeKind::NewShow,
+            Comparison::Changed(ShowComparison::NewSeasons(..)) => ChangeKind::NewSeasons,
+            Comparison::Changed(ShowComparison::NewEpisodes(..)) => ChangeKind::NewEpisodes,
+        }
+    }
+
+    /// The show as it is currently on disk
+    pub fn show(&self) -> &Show {
+        match self {
+            Comparison::NewlyAdded(show) => show,
+            Comparison::Changed(ShowComparison::NewSeasons(show, _)) => show,
+            Comparison::Changed(ShowComparison::NewEpisodes(show, _)) => show,
+        }
+    }
 }
\ No newline at end of file
@@ -1,43 +1,216 @@
 #![allow(dead_code)]
 
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-fn null() -> String {
-    String::from("null")
+/// Do not include any embeds when serializing this message
+pub const FLAG_SUPPRESS_EMBEDS: u64 = 1 << 2;
+/// Do not trigger push and desktop notifications
+pub const FLAG_SUPPRESS_NOTIFICATIONS: u64 = 1 << 12;
+
+// Documented limits of the execute webhook endpoint, counted in characters
+const MAX_CONTENT: usize = 2000;
+const MAX_USERNAME: usize = 80;
+const MAX_THREAD_NAME: usize = 100;
+const MAX_EMBEDS: usize = 10;
+const MAX_TITLE: usize = 256;
+const MAX_DESCRIPTION: usize = 4096;
+const MAX_FIELDS: usize = 25;
+const MAX_FIELD_NAME: usize = 256;
+const MAX_FIELD_VALUE: usize = 1024;
+const MAX_FOOTER_TEXT: usize = 2048;
+const MAX_AUTHOR_NAME: usize = 256;
+const MAX_EMBEDS_TOTAL: usize = 6000;
+
+#[derive(Debug)]
+pub enum WebhookError {
+    /// Neither content nor embeds or attachments given
+    Empty,
+    TooLong { field: &'static str, length: usize, max: usize },
+    TooMany { field: &'static str, count: usize, max: usize },
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::Empty => write!(f, "webhook has no content, embeds or attachments"),
+            WebhookError::TooLong { field, length, max } => write!(f, "{field} is {length} characters long, at most {max} are allowed"),
+            WebhookError::TooMany { field, count, max } => write!(f, "{count} {field} given, at most {max} are allowed"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+fn check_length(field: &'static str, value: &str, max: usize) -> Result<usize, WebhookError> {
+    let length = value.chars().count();
+
+    if length > max {
+        return Err(WebhookError::TooLong { field, length, max });
+    }
+
+    Ok(length)
+}
+
+fn check_count(field: &'static str, count: usize, max: usize) -> Result<(), WebhookError> {
+    if count > max {
+        return Err(WebhookError::TooMany { field, count, max });
+    }
+
+    Ok(())
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Webhook {
-    #[serde(default = "null")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     content: String,
+    #[serde(default)]
     embeds: Vec<Embed>,
     username: Option<String>,
-    avatar_url: Option<String>
+    avatar_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tts: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_mentions: Option<AllowedMentions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u64>,
+    /// Creates a forum post with this name, only valid for forum channels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attachments: Option<Vec<Attachment>>,
 }
 
 impl Webhook {
     pub fn builder() -> WebhookBuilder {
-        WebhookBuilder { content: None, embeds: None, username: None, avatar_url: None }
+        WebhookBuilder {
+            content: None,
+            embeds: None,
+            username: None,
+            avatar_url: None,
+            tts: None,
+            allowed_mentions: None,
+            flags: None,
+            thread_name: None,
+            attachments: None,
+        }
     }
 
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).expect("Failed to stringify webhook object")
     }
+
+    /// Check the message against the limits Discord enforces
+    pub fn validate(&self) -> Result<(), WebhookError> {
+        let has_attachments = self.attachments.as_ref().is_some_and(|a| !a.is_empty());
+
+        if self.content.is_empty() && self.embeds.is_empty() && !has_attachments {
+            return Err(WebhookError::Empty);
+        }
+
+        check_length("content", &self.content, MAX_CONTENT)?;
+
+        if let Some(username) = &self.username {
+            check_length("username", username, MAX_USERNAME)?;
+        }
+
+        if let Some(thread_name) = &self.thread_name {
+            check_length("thread_name", thread_name, MAX_THREAD_NAME)?;
+        }
+
+        check_count("embeds", self.embeds.len(), MAX_EMBEDS)?;
+
+        let mut total = 0;
+
+        for embed in &self.embeds {
+            total += embed.validate()?;
+        }
+
+        if total > MAX_EMBEDS_TOTAL {
+            return Err(WebhookError::TooLong { field: "embeds", length: total, max: MAX_EMBEDS_TOTAL });
+        }
+
+        Ok(())
+    }
+}
+
+/// Message returned when executing a webhook with `wait=true`
+#[derive(Deserialize)]
+pub struct WebhookMessage {
+    pub id: String,
+    pub channel_id: String,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Embed {
     title: Option<String>,
     description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// ISO8601 timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
     color: Option<usize>,
     fields: Option<Vec<Field>>,
     author: Option<Author>,
     footer: Option<Footer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Media>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<Media>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider: Option<Provider>,
 }
 
 impl Embed {
     pub fn builder() -> EmbedBuilder {
-        EmbedBuilder { title: None, description: None, color: None, fields: None, author: None, footer: None }
+        EmbedBuilder {
+            title: None,
+            description: None,
+            url: None,
+            timestamp: None,
+            color: None,
+            fields: None,
+            author: None,
+            footer: None,
+            image: None,
+            thumbnail: None,
+            provider: None,
+        }
+    }
+
+    /// Check the embed limits, returns the characters counting towards the total limit
+    fn validate(&self) -> Result<usize, WebhookError> {
+        let mut total = 0;
+
+        if let Some(title) = &self.title {
+            total += check_length("title", title, MAX_TITLE)?;
+        }
+
+        if let Some(description) = &self.description {
+            total += check_length("description", description, MAX_DESCRIPTION)?;
+        }
+
+        if let Some(fields) = &self.fields {
+            check_count("fields", fields.len(), MAX_FIELDS)?;
+
+            for field in fields {
+                total += check_length("field name", &field.name, MAX_FIELD_NAME)?;
+                total += check_length("field value", &field.value, MAX_FIELD_VALUE)?;
+            }
+        }
+
+        if let Some(footer) = &self.footer {
+            total += check_length("footer text", &footer.text, MAX_FOOTER_TEXT)?;
+        }
+
+        if let Some(author) = &self.author {
+            total += check_length("author name", &author.name, MAX_AUTHOR_NAME)?;
+        }
+
+        Ok(total)
     }
 }
 
@@ -82,11 +255,82 @@ impl Footer {
     }
 }
 
+/// Image or thumbnail of an embed
+#[derive(Deserialize, Serialize)]
+pub struct Media {
+    /// http(s) url or `attachment://<filename>`
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxy_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<usize>,
+}
+
+impl Media {
+    pub fn builder(url: String) -> MediaBuilder {
+        MediaBuilder { url, proxy_url: None, height: None, width: None }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Provider {
+    name: Option<String>,
+    url: Option<String>,
+}
+
+impl Provider {
+    pub fn builder() -> ProviderBuilder {
+        ProviderBuilder { name: None, url: None }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AllowedMentions {
+    /// Any of `roles`, `users` and `everyone`
+    parse: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    roles: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    users: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replied_user: Option<bool>,
+}
+
+impl AllowedMentions {
+    /// Starts out allowing no mentions at all
+    pub fn builder() -> AllowedMentionsBuilder {
+        AllowedMentionsBuilder { parse: Vec::new(), roles: Vec::new(), users: Vec::new(), replied_user: None }
+    }
+}
+
+/// Metadata of a file uploaded along with the message
+#[derive(Deserialize, Serialize)]
+pub struct Attachment {
+    /// Index of the `files[n]` multipart field
+    id: usize,
+    filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl Attachment {
+    pub fn builder(id: usize, filename: String) -> AttachmentBuilder {
+        AttachmentBuilder { id, filename, description: None }
+    }
+}
+
 pub struct WebhookBuilder {
     content: Option<String>,
     embeds: Option<Vec<Embed>>,
     username: Option<String>,
     avatar_url: Option<String>,
+    tts: Option<bool>,
+    allowed_mentions: Option<AllowedMentions>,
+    flags: Option<u64>,
+    thread_name: Option<String>,
+    attachments: Option<Vec<Attachment>>,
 }
 
 impl WebhookBuilder {
@@ -110,23 +354,63 @@ impl WebhookBuilder {
         self
     }
 
-    pub fn build(self) -> Webhook {
-        Webhook {
-            content: self.content.unwrap_or(String::from("null")),
-            embeds: self.embeds.expect("No embeds given to WebhookBuilder"),
+    pub fn tts(mut self, tts: bool) -> WebhookBuilder {
+        self.tts = Some(tts);
+        self
+    }
+
+    pub fn allowed_mentions(mut self, allowed_mentions: AllowedMentions) -> WebhookBuilder {
+        self.allowed_mentions = Some(allowed_mentions);
+        self
+    }
+
+    /// Bitfield of `FLAG_*` constants
+    pub fn flags(mut self, flags: u64) -> WebhookBuilder {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn thread_name(mut self, thread_name: String) -> WebhookBuilder {
+        self.thread_name = Some(thread_name);
+        self
+    }
+
+    pub fn attachments(mut self, attachments: Vec<Attachment>) -> WebhookBuilder {
+        self.attachments = Some(attachments);
+        self
+    }
+
+    /// Build the webhook, fails if it exceeds any of Discord's limits
+    pub fn build(self) -> Result<Webhook, WebhookError> {
+        let webhook = Webhook {
+            content: self.content.unwrap_or_default(),
+            embeds: self.embeds.unwrap_or_default(),
             username: self.username,
             avatar_url: self.avatar_url,
-        }
+            tts: self.tts,
+            allowed_mentions: self.allowed_mentions,
+            flags: self.flags,
+            thread_name: self.thread_name,
+            attachments: self.attachments,
+        };
+
+        webhook.validate()?;
+        Ok(webhook)
     }
 }
 
 pub struct EmbedBuilder {
     title: Option<String>,
     description: Option<String>,
+    url: Option<String>,
+    timestamp: Option<String>,
     color: Option<usize>,
     fields: Option<Vec<Field>>,
     author: Option<Author>,
     footer: Option<Footer>,
+    image: Option<Media>,
+    thumbnail: Option<Media>,
+    provider: Option<Provider>,
 }
 
 impl EmbedBuilder {
@@ -140,6 +424,16 @@ impl EmbedBuilder {
         self
     }
 
+    pub fn url(mut self, url: String) -> EmbedBuilder {
+        self.url = Some(url);
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> EmbedBuilder {
+        self.timestamp = Some(timestamp.to_rfc3339());
+        self
+    }
+
     pub fn color(mut self, color: usize) -> EmbedBuilder {
         self.color = Some(color);
         self
@@ -160,14 +454,34 @@ impl EmbedBuilder {
         self
     }
 
+    pub fn image(mut self, image: Media) -> EmbedBuilder {
+        self.image = Some(image);
+        self
+    }
+
+    pub fn thumbnail(mut self, thumbnail: Media) -> EmbedBuilder {
+        self.thumbnail = Some(thumbnail);
+        self
+    }
+
+    pub fn provider(mut self, provider: Provider) -> EmbedBuilder {
+        self.provider = Some(provider);
+        self
+    }
+
     pub fn build(self) -> Embed {
         Embed {
             title: self.title,
             description: self.description,
+            url: self.url,
+            timestamp: self.timestamp,
             color: self.color,
             fields: self.fields,
             author: self.author,
             footer: self.footer,
+            image: self.image,
+            thumbnail: self.thumbnail,
+            provider: self.provider,
         }
     }
 }
@@ -237,4 +551,181 @@ impl FooterBuilder {
     pub fn build(self) -> Footer {
         Footer { text: self.text, icon_url: self.icon_url, proxy_icon_url: self.proxy_icon_url }
     }
-}
\ No newline at end of file
+}
+
+pub struct MediaBuilder {
+    url: String,
+    proxy_url: Option<String>,
+    height: Option<usize>,
+    width: Option<usize>,
+}
+
+impl MediaBuilder {
+    pub fn proxy_url(mut self, proxy_url: String) -> MediaBuilder {
+        self.proxy_url = Some(proxy_url);
+        self
+    }
+
+    pub fn height(mut self, height: usize) -> MediaBuilder {
+        self.height = Some(height);
+        self
+    }
+
+    pub fn width(mut self, width: usize) -> MediaBuilder {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn build(self) -> Media {
+        Media { url: self.url, proxy_url: self.proxy_url, height: self.height, width: self.width }
+    }
+}
+
+pub struct ProviderBuilder {
+    name: Option<String>,
+    url: Option<String>,
+}
+
+impl ProviderBuilder {
+    pub fn name(mut self, name: String) -> ProviderBuilder {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn url(mut self, url: String) -> ProviderBuilder {
+        self.url = Some(url);
+        self
+    }
+
+    pub fn build(self) -> Provider {
+        Provider { name: self.name, url: self.url }
+    }
+}
+
+pub struct AllowedMentionsBuilder {
+    parse: Vec<String>,
+    roles: Vec<String>,
+    users: Vec<String>,
+    replied_user: Option<bool>,
+}
+
+impl AllowedMentionsBuilder {
+    /// Allow a whole mention type, one of `roles`, `users` or `everyone`
+    pub fn parse(mut self, kind: String) -> AllowedMentionsBuilder {
+        self.parse.push(kind);
+        self
+    }
+
+    pub fn role(mut self, id: String) -> AllowedMentionsBuilder {
+        self.roles.push(id);
+        self
+    }
+
+    pub fn user(mut self, id: String) -> AllowedMentionsBuilder {
+        self.users.push(id);
+        self
+    }
+
+    pub fn replied_user(mut self, replied_user: bool) -> AllowedMentionsBuilder {
+        self.replied_user = Some(replied_user);
+        self
+    }
+
+    pub fn build(self) -> AllowedMentions {
+        AllowedMentions { parse: self.parse, roles: self.roles, users: self.users, replied_user: self.replied_user }
+    }
+}
+
+pub struct AttachmentBuilder {
+    id: usize,
+    filename: String,
+    description: Option<String>,
+}
+
+impl AttachmentBuilder {
+    pub fn description(mut self, description: String) -> AttachmentBuilder {
+        self.description = Some(description);
+        self
+    }
+
+    pub fn build(self) -> Attachment {
+        Attachment { id: self.id, filename: self.filename, description: self.description }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_webhook_is_rejected() {
+        assert!(matches!(Webhook::builder().build(), Err(WebhookError::Empty)));
+        assert!(matches!(Webhook::builder().content(String::new()).embeds(Vec::new()).build(), Err(WebhookError::Empty)));
+    }
+
+    #[test]
+    fn content_is_optional() {
+        let webhook = Webhook::builder()
+            .embeds(vec![Embed::builder().title(String::from("Title")).build()])
+            .build()
+            .unwrap();
+
+        assert!(!webhook.to_json().contains("\"content\""));
+    }
+
+    #[test]
+    fn attachments_alone_are_enough() {
+        let webhook = Webhook::builder()
+            .attachments(vec![Attachment::builder(0, String::from("cover.png")).build()])
+            .build()
+            .unwrap();
+
+        assert!(webhook.to_json().contains("\"embeds\":[]"));
+    }
+
+    #[test]
+    fn content_limit() {
+        assert!(Webhook::builder().content("a".repeat(MAX_CONTENT)).build().is_ok());
+        assert!(matches!(
+            Webhook::builder().content("a".repeat(MAX_CONTENT + 1)).build(),
+            Err(WebhookError::TooLong { field: "content", length: 2001, max: MAX_CONTENT })
+        ));
+    }
+
+    #[test]
+    fn limits_count_characters_not_bytes() {
+        assert!(Webhook::builder().content("ä".repeat(MAX_CONTENT)).build().is_ok());
+    }
+
+    #[test]
+    fn embed_count_limit() {
+        let embeds = (0..=MAX_EMBEDS).map(|_| Embed::builder().title(String::from("Title")).build()).collect();
+
+        assert!(matches!(
+            Webhook::builder().embeds(embeds).build(),
+            Err(WebhookError::TooMany { field: "embeds", count: 11, max: MAX_EMBEDS })
+        ));
+    }
+
+    #[test]
+    fn field_count_limit() {
+        let fields = (0..=MAX_FIELDS).map(|i| Field::builder(i.to_string(), i.to_string()).build()).collect();
+        let embed = Embed::builder().fields(fields).build();
+
+        assert!(matches!(
+            Webhook::builder().embeds(vec![embed]).build(),
+            Err(WebhookError::TooMany { field: "fields", count: 26, max: MAX_FIELDS })
+        ));
+    }
+
+    #[test]
+    fn embeds_total_limit() {
+        // Every embed is within its own limits, together they exceed the total
+        let embeds = (0..2).map(|_| Embed::builder().description("a".repeat(MAX_DESCRIPTION)).build()).collect();
+
+        assert!(matches!(
+            Webhook::builder().embeds(embeds).build(),
+            Err(WebhookError::TooLong { field: "embeds", length: 8192, max: MAX_EMBEDS_TOTAL })
+        ));
+    }
+}